@@ -0,0 +1,6561 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::time::Duration;
+use std::vec;
+
+use bevy::asset::LoadState;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::render::view::screenshot::ScreenshotManager;
+use bevy::sprite::{MaterialMesh2dBundle, Mesh2dHandle};
+use bevy::time::common_conditions::on_timer;
+use bevy::time::Stopwatch;
+use bevy::window::{PrimaryWindow, ReceivedCharacter, WindowResized};
+use bevy::winit::WinitWindows;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use winit::window::Icon;
+
+const SNAKE_HEAD_COLOR: Color = Color::rgb(0.7, 0.7, 0.7);
+const SNAKE_SEGMENT_COLOR: Color = Color::rgb(0.3, 0.3, 0.3);
+pub const WINDOW_TITLE: &str = "Snake Game";
+const WINDOW_ICON_PATH: &str = "icon.png";
+const SETTINGS_PATH: &str = "settings.cfg";
+const PROFILE_PATH: &str = "profile.json";
+const HUD_FONT_PATH: &str = "fonts/hud.ttf";
+const ARENA_WIDTH: u32 = 10;
+const ARENA_HEIGHT: u32 = 10;
+const WALL_COLOR: Color = Color::rgb(0.45, 0.3, 0.2);
+const FOOD_COLOR: Color = Color::rgb(1.0, 0.0, 1.0);
+const FOOD_ALT_COLOR: Color = Color::rgb(0.0, 1.0, 1.0);
+const FOOD_COMMON_COLOR: Color = Color::rgb(1.0, 1.0, 1.0);
+const FOOD_UNCOMMON_COLOR: Color = Color::rgb(0.2, 0.4, 1.0);
+const FOOD_RARE_COLOR: Color = Color::rgb(1.0, 0.8, 0.1);
+const FOOD_COMMON_WEIGHT: u32 = 80;
+const FOOD_UNCOMMON_WEIGHT: u32 = 15;
+const FOOD_RARE_WEIGHT: u32 = 5;
+const NOKIA_LCD_COLOR: Color = Color::rgb(0.61, 0.73, 0.45);
+const NOKIA_PIXEL_COLOR: Color = Color::rgb(0.17, 0.22, 0.12);
+const BACKGROUND_TOP_COLOR: Color = Color::rgb(0.2, 0.22, 0.28);
+const BACKGROUND_BOTTOM_COLOR: Color = Color::rgb(0.07, 0.07, 0.09);
+/// Just inside the 2D camera's far plane, behind every board sprite at z 0.
+const BACKGROUND_Z: f32 = -0.05;
+const TARGET_COLOR_PERIOD: f32 = 10.0;
+const FLICKER_FOOD_CHANCE: f64 = 0.1;
+const SPLITTER_FOOD_CHANCE: f64 = 0.05;
+/// Splitting never pushes the board past this much food.
+const MAX_FOOD: usize = 6;
+const TRAP_EDGE_WEIGHT: u32 = 3;
+const TRAP_CORNER_WEIGHT: u32 = 4;
+const TRAP_TAIL_WEIGHT: u32 = 4;
+const TRAP_TAIL_RADIUS: i32 = 2;
+const STRAIGHT_SHOT_BONUS: u32 = 3;
+const INPUT_BUFFER_SIZE: usize = 2;
+const DEATH_SLOW_MO_INTERVAL: f32 = 0.5;
+const DEATH_FREEZE_SECONDS: f32 = 0.6;
+const PATH_REVIEW_SECONDS: f32 = 4.0;
+/// Cells the snake slides on after a crash, over the first half of the
+/// death freeze.
+const COAST_TICKS: f32 = 2.0;
+/// Movement ticks between steps of fleeing food.
+const PREY_STEP_TICKS: u64 = 3;
+const SHAKE_INTENSITY: f32 = 8.0;
+const SHAKE_SECONDS: f32 = 0.3;
+const POWER_UP_COLOR: Color = Color::rgb(1.0, 0.85, 0.1);
+const POWER_UP_SPAWN_SECONDS: u64 = 15;
+const EFFECT_GLOW_PULSES_PER_SECOND: f32 = 1.5;
+const EFFECT_GLOW_STRENGTH: f32 = 0.6;
+const BIG_HEAD_SECONDS: f32 = 8.0;
+const SPAWN_PROTECTION_SECONDS: f32 = 1.5;
+const SHIELD_FLASHES_PER_SECOND: f32 = 6.0;
+const HEAD_SIZE: f32 = 0.8;
+const BIG_HEAD_SIZE: f32 = 1.8;
+const PATH_MARKER_COLOR: Color = Color::rgba(1.0, 1.0, 1.0, 0.25);
+const EXPLODE_DISTANCE: f32 = 3.0;
+const WALL_GRACE_COLOR: Color = Color::rgb(1.0, 0.1, 0.1);
+const WALL_GRACE_FLASHES_PER_SECOND: f32 = 8.0;
+const BLINK_COUNT: f32 = 4.0;
+const GOAL_BAR_COLOR: Color = Color::rgb(0.2, 0.8, 0.2);
+const GOAL_BAR_BACKGROUND: Color = Color::rgba(0.3, 0.3, 0.3, 0.6);
+const POPUP_SECONDS: f32 = 1.0;
+const POPUP_RISE_SPEED: f32 = 40.0;
+const MAX_EFFECTS: usize = 32;
+const KIOSK_COUNTDOWN_SECONDS: f32 = 10.0;
+const FOOD_PULL_SECONDS: f32 = 0.12;
+const RUN_CODE_DIGITS: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const FOOD_HEADING_GUARD: i32 = 2;
+const MAX_EAT_RADIUS: u32 = 3;
+const MOVEMENT_INTERVAL: f32 = 0.150;
+const AUTO_BALANCE_SLOWEST: f32 = 0.200;
+const AUTO_BALANCE_FASTEST: f32 = 0.080;
+const AUTO_BALANCE_TARGET_TICKS: f32 = 600.0;
+const AUTO_BALANCE_LIVES: usize = 5;
+const SPEED_TINT_COLOR: Color = Color::rgb(1.0, 0.1, 0.1);
+const SPEED_TINT_FASTEST_INTERVAL: f32 = 0.050;
+const ZEN_INTERVAL: f32 = 0.200;
+const HARDCORE_INTERVAL: f32 = 0.090;
+const TIME_ATTACK_SECONDS: u64 = 90;
+const SPEED_TINT_STRENGTH: f32 = 0.6;
+const MOTION_BLUR_COLOR: Color = Color::rgba(0.7, 0.7, 0.7, 0.35);
+const MOTION_BLUR_MIN_SPEED: f32 = 8.0;
+const MOTION_BLUR_MAX_LENGTH: f32 = 1.5;
+const WIGGLE_AMPLITUDE: f32 = 0.06;
+const NEXT_CELL_COLOR: Color = Color::rgba(1.0, 1.0, 1.0, 0.35);
+const NEXT_CELL_EDGE: f32 = 0.08;
+const SAFE_PATH_COLOR: Color = Color::rgba(0.3, 1.0, 0.4, 0.12);
+/// Upper bound on the cells one flood fill may visit.
+const FLOOD_FILL_LIMIT: usize = 4096;
+const WIGGLE_SPEED: f32 = 6.0;
+const WIGGLE_PHASE_STEP: f32 = 0.9;
+const TAIL_GRAZE_BONUS: u32 = 5;
+const SNAKE_SPRITES_PATH: &str = "sprites/snake.png";
+const SNAKE_SPRITE_TILE: f32 = 32.0;
+const FRAME_CAPTURE_DIR: &str = "frames";
+const FRAME_CAPTURE_MAX: u32 = 2000;
+const INPUT_ARROW_IDLE_COLOR: Color = Color::rgba(0.3, 0.3, 0.3, 0.6);
+const INPUT_ARROW_ACTIVE_COLOR: Color = Color::rgb(1.0, 0.0, 1.0);
+
+#[derive(States, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+enum GameState {
+    #[default]
+    Playing,
+    Won,
+    /// Corpse walls have left no room for a new snake.
+    Buried,
+}
+
+#[derive(Component)]
+struct SnakeHead {
+    direction: Direction,
+}
+
+#[derive(Component)]
+struct SnakeSegment;
+
+/// Outline drawn on the head's next cell while `ShowNextCell` is on.
+#[derive(Component)]
+struct NextCellGhost;
+
+/// Full-window quad drawing the theme's vertical gradient.
+#[derive(Component)]
+struct Background;
+
+/// Faint tint over a cell of the largest open region next to the head.
+#[derive(Component)]
+struct SafePathTile;
+
+/// Streak sprite parented to the head, stretched behind it at high speeds.
+#[derive(Component)]
+struct MotionBlurStreak;
+
+#[derive(Default, Resource)]
+struct SnakeSegments(Vec<Entity>);
+
+#[derive(Component)]
+struct InputVisualizerRoot;
+
+/// One arrow of the input visualizer, lit while its key is held.
+#[derive(Component)]
+struct InputArrow(KeyCode);
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+impl Direction {
+    const ALL: [Self; 4] = [Self::Left, Self::Right, Self::Up, Self::Down];
+
+    fn opposite(self) -> Self {
+        match self {
+            Self::Left => Self::Right,
+            Self::Right => Self::Left,
+            Self::Up => Self::Down,
+            Self::Down => Self::Up,
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "left" => Some(Self::Left),
+            "right" => Some(Self::Right),
+            "up" => Some(Self::Up),
+            "down" => Some(Self::Down),
+            _ => None,
+        }
+    }
+
+    /// A quarter turn counter-clockwise.
+    fn rotated_left(self) -> Self {
+        match self {
+            Self::Up => Self::Left,
+            Self::Left => Self::Down,
+            Self::Down => Self::Right,
+            Self::Right => Self::Up,
+        }
+    }
+
+    fn rotated_right(self) -> Self {
+        self.rotated_left().opposite()
+    }
+
+    /// One cell in this direction, in board space.
+    fn unit(self) -> Vec2 {
+        match self {
+            Self::Left => Vec2::NEG_X,
+            Self::Right => Vec2::X,
+            Self::Up => Vec2::Y,
+            Self::Down => Vec2::NEG_Y,
+        }
+    }
+
+    /// Same glyphs as the input visualizer arrows.
+    fn arrow(self) -> &'static str {
+        match self {
+            Self::Up => "^",
+            Self::Right => ">",
+            Self::Down => "v",
+            Self::Left => "<",
+        }
+    }
+
+    /// Clockwise order starting at `Up`, matching the sprite-sheet columns.
+    fn index(self) -> usize {
+        match self {
+            Self::Up => 0,
+            Self::Right => 1,
+            Self::Down => 2,
+            Self::Left => 3,
+        }
+    }
+}
+
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Position {
+    x: i32,
+    y: i32,
+}
+
+impl Position {
+    fn step(self, direction: Direction) -> Self {
+        match direction {
+            Direction::Left => Self {
+                x: self.x - 1,
+                ..self
+            },
+            Direction::Right => Self {
+                x: self.x + 1,
+                ..self
+            },
+            Direction::Up => Self {
+                y: self.y + 1,
+                ..self
+            },
+            Direction::Down => Self {
+                y: self.y - 1,
+                ..self
+            },
+        }
+    }
+
+    fn manhattan(self, to: Position) -> u32 {
+        (to.x - self.x).unsigned_abs() + (to.y - self.y).unsigned_abs()
+    }
+
+    /// Direction of a single step from `self` to `to`, if they are neighbours.
+    fn direction_to(self, to: Position) -> Option<Direction> {
+        match (to.x - self.x, to.y - self.y) {
+            (-1, 0) => Some(Direction::Left),
+            (1, 0) => Some(Direction::Right),
+            (0, 1) => Some(Direction::Up),
+            (0, -1) => Some(Direction::Down),
+            _ => None,
+        }
+    }
+}
+
+fn in_arena(pos: Position) -> bool {
+    pos.x >= 0 && pos.y >= 0 && (pos.x as u32) < ARENA_WIDTH && (pos.y as u32) < ARENA_HEIGHT
+}
+
+fn arena_cells() -> impl Iterator<Item = Position> {
+    (0..ARENA_WIDTH as i32).flat_map(|x| (0..ARENA_HEIGHT as i32).map(move |y| Position { x, y }))
+}
+
+fn arena_center() -> Position {
+    Position {
+        x: ARENA_WIDTH as i32 / 2,
+        y: ARENA_HEIGHT as i32 / 2,
+    }
+}
+
+#[derive(Resource, Default)]
+struct LastTailPosition(Option<Position>);
+
+/// Where the snake spawns and which way it faces; its first segment trails
+/// behind the head. A `cell` of `None` starts at the arena center. Set with
+/// `start` in `SETTINGS_PATH` to a cell, a heading or both, e.g.
+/// `start=3,4 right`.
+#[derive(Resource)]
+struct Start {
+    cell: Option<Position>,
+    direction: Direction,
+}
+
+impl Default for Start {
+    fn default() -> Self {
+        Self {
+            cell: None,
+            direction: Direction::Up,
+        }
+    }
+}
+
+impl Start {
+    fn from_settings(settings: &Settings) -> Result<Self, SnakeError> {
+        settings
+            .get(
+                "start",
+                "x,y inside the arena and/or up, down, left or right",
+                |value| {
+                    let mut start = Self::default();
+                    for word in value.split_whitespace() {
+                        if let Some(direction) = Direction::parse(word) {
+                            start.direction = direction;
+                            continue;
+                        }
+                        let (x, y) = word.split_once(',')?;
+                        let cell = Position {
+                            x: x.parse().ok()?,
+                            y: y.parse().ok()?,
+                        };
+                        if !in_arena(cell) {
+                            return None;
+                        }
+                        start.cell = Some(cell);
+                    }
+                    Some(start)
+                },
+            )
+            .map(Option::unwrap_or_default)
+    }
+}
+
+/// Where the snake comes back after dying: the configured start, or the
+/// nearest cell to where it died that leaves room to turn. Set with
+/// `respawn=start` or `respawn=near_death` in `SETTINGS_PATH`.
+#[derive(Resource, Default, Clone, Copy, PartialEq)]
+enum RespawnPolicy {
+    #[default]
+    Start,
+    NearDeath,
+}
+
+impl RespawnPolicy {
+    fn from_settings(settings: &Settings) -> Result<Self, SnakeError> {
+        settings
+            .get("respawn", "start or near_death", |value| match value {
+                "start" => Some(Self::Start),
+                "near_death" => Some(Self::NearDeath),
+                _ => None,
+            })
+            .map(Option::unwrap_or_default)
+    }
+}
+
+/// Saves the window to a numbered PNG in `FRAME_CAPTURE_DIR` on every movement
+/// tick. Off unless `SNAKE_RECORD_FRAMES=1`, and stops at `FRAME_CAPTURE_MAX`.
+#[derive(Resource, Default)]
+struct FrameCapture {
+    enabled: bool,
+    frame: u32,
+}
+
+impl FrameCapture {
+    fn from_env() -> Self {
+        Self {
+            enabled: std::env::var("SNAKE_RECORD_FRAMES").is_ok_and(|v| v == "1"),
+            frame: 0,
+        }
+    }
+}
+
+/// Developer diagnostics, enabled with `SNAKE_DEBUG=1`.
+#[derive(Resource, Default)]
+struct DebugMode(bool);
+
+impl DebugMode {
+    fn from_env() -> Self {
+        Self(std::env::var("SNAKE_DEBUG").is_ok_and(|v| v == "1"))
+    }
+}
+
+/// Debug single-stepping under `SNAKE_DEBUG`: P pauses movement, and while
+/// paused each press of period advances exactly one movement tick.
+#[derive(Resource, Default)]
+struct StepMode {
+    paused: bool,
+    /// Set for the frame a step was asked for.
+    step_requested: bool,
+}
+
+/// A snake layout from a bug report, replayed with R under `SNAKE_DEBUG`.
+struct Scenario {
+    direction: Direction,
+    /// Head first.
+    cells: Vec<Position>,
+    food: Option<Position>,
+}
+
+/// Read from `SNAKE_SCENARIO`: the heading, the cells from head to tail and
+/// optionally `food` and its cell, e.g. `right 5,5 4,5 3,5 food 8,5`.
+#[derive(Resource, Default)]
+struct DebugScenario(Option<Scenario>);
+
+impl DebugScenario {
+    fn from_env() -> Self {
+        let Ok(layout) = std::env::var("SNAKE_SCENARIO") else {
+            return Self::default();
+        };
+        Self(
+            Scenario::parse(&layout)
+                .map_err(|err| warn!("ignoring SNAKE_SCENARIO: {err}"))
+                .ok(),
+        )
+    }
+}
+
+impl Scenario {
+    fn parse(layout: &str) -> Result<Self, String> {
+        let cell = |word: &str| -> Result<Position, String> {
+            let (x, y) = word
+                .split_once(',')
+                .ok_or_else(|| format!("expected a cell like 3,4, found {word:?}"))?;
+            let parse = |n: &str| n.trim().parse().map_err(|_| format!("bad cell {word:?}"));
+            Ok(Position {
+                x: parse(x)?,
+                y: parse(y)?,
+            })
+        };
+
+        let mut words = layout.split_whitespace();
+        let heading = words.next().unwrap_or_default();
+        let direction =
+            Direction::parse(heading).ok_or_else(|| format!("unknown heading {heading:?}"))?;
+        let mut cells = Vec::new();
+        let mut food = None;
+        while let Some(word) = words.next() {
+            if word == "food" {
+                food = Some(cell(words.next().unwrap_or_default())?);
+            } else {
+                cells.push(cell(word)?);
+            }
+        }
+        Ok(Self {
+            direction,
+            cells,
+            food,
+        })
+    }
+}
+
+/// Debug switches for individual gameplay systems, flipped with the number
+/// keys under `SNAKE_DEBUG`.
+#[derive(Resource)]
+struct SystemToggles {
+    food_spawner: bool,
+    movement: bool,
+    eating: bool,
+    collision: bool,
+}
+
+impl Default for SystemToggles {
+    fn default() -> Self {
+        Self {
+            food_spawner: true,
+            movement: true,
+            eating: true,
+            collision: true,
+        }
+    }
+}
+
+impl SystemToggles {
+    fn disabled(&self) -> Vec<&'static str> {
+        [
+            ("food_spawner", self.food_spawner),
+            ("movement", self.movement),
+            ("eating", self.eating),
+            ("collision", self.collision),
+        ]
+        .into_iter()
+        .filter(|(_, enabled)| !enabled)
+        .map(|(name, _)| name)
+        .collect()
+    }
+}
+
+/// The window icon while it loads; cleared once applied or if it is missing.
+#[derive(Resource)]
+struct WindowIcon(Option<Handle<Image>>);
+
+/// The font every HUD and menu text uses. Falls back to Bevy's built-in font
+/// when `HUD_FONT_PATH` can't be loaded.
+#[derive(Resource)]
+struct FontHandle(Handle<Font>);
+
+impl FromWorld for FontHandle {
+    fn from_world(world: &mut World) -> Self {
+        Self(world.resource::<AssetServer>().load(HUD_FONT_PATH))
+    }
+}
+
+impl FontHandle {
+    fn style(&self, font_size: f32, color: Color) -> TextStyle {
+        TextStyle {
+            font: self.0.clone(),
+            font_size,
+            color,
+        }
+    }
+}
+
+/// Sprite-sheet for the snake: a 4x4 grid of `SNAKE_SPRITE_TILE` cells.
+///
+/// Row 0 holds the head and row 1 the tail, each facing up, right, down and
+/// left. Row 2 starts with the vertical and horizontal body pieces, followed
+/// by the corners joining up-right, right-down, down-left and left-up, the
+/// last two of which wrap onto row 3.
+#[derive(Resource)]
+struct SnakeSprites {
+    image: Handle<Image>,
+    layout: Handle<TextureAtlasLayout>,
+}
+
+#[derive(Resource, Default)]
+struct Score(u32);
+
+/// Food eaten in a row since the last death, shown on the HUD and paid out
+/// by `StreakBonus`.
+#[derive(Resource, Default)]
+struct Streak(u32);
+
+/// The last direction the input system accepted. Holding a key keeps
+/// reporting it as pressed, so it only registers again once it changes.
+#[derive(Resource, Default)]
+struct LastRegisteredDir(Option<Direction>);
+
+/// Turns waiting to be applied, one per movement tick. Each one is checked
+/// against the heading the turn before it will leave the snake in, so two
+/// quick turns into an S-bend can't add up to a reversal.
+#[derive(Resource, Default)]
+pub struct InputBuffer(pub VecDeque<Direction>);
+
+/// Speedrun challenge: the run is won the moment the snake reaches this many
+/// segments. Set with `SNAKE_LENGTH_GOAL`.
+#[derive(Resource, Default)]
+struct LengthGoal(Option<usize>);
+
+impl LengthGoal {
+    fn from_env() -> Self {
+        Self(
+            std::env::var("SNAKE_LENGTH_GOAL")
+                .ok()
+                .and_then(|goal| goal.parse().ok()),
+        )
+    }
+}
+
+/// Kiosk/demo limit: a run older than this, in any state, is reset to a fresh
+/// board. Set in seconds with `SNAKE_MAX_RUN_SECONDS`; unlimited by default.
+#[derive(Resource, Default)]
+struct MaxRunDuration(Option<Duration>);
+
+impl MaxRunDuration {
+    fn from_env() -> Self {
+        Self(
+            std::env::var("SNAKE_MAX_RUN_SECONDS")
+                .ok()
+                .and_then(|secs| secs.parse().ok())
+                .map(Duration::from_secs_f32),
+        )
+    }
+}
+
+/// Lifetime stats, kept in `PROFILE_PATH` and saved at the end of every run.
+#[derive(Resource, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Profile {
+    games_played: u32,
+    apples_eaten: u32,
+    play_time_secs: f32,
+    best_score: u32,
+}
+
+impl Profile {
+    /// A missing profile starts fresh.
+    fn load() -> Result<Self, SnakeError> {
+        let Some(contents) = read_optional(PROFILE_PATH)? else {
+            return Ok(Self::default());
+        };
+        serde_json::from_str(&contents).map_err(|err| SnakeError::Parse {
+            path: PROFILE_PATH,
+            line: err.line(),
+            message: err.to_string(),
+        })
+    }
+
+    fn save(&self) {
+        let saved = serde_json::to_string_pretty(self)
+            .map_err(|err| err.to_string())
+            .and_then(|json| std::fs::write(PROFILE_PATH, json).map_err(|err| err.to_string()));
+        if let Err(err) = saved {
+            warn!("cannot save {PROFILE_PATH}: {err}");
+        }
+    }
+}
+
+/// Time spent playing the current run.
+#[derive(Resource, Default)]
+struct RunClock(Stopwatch);
+
+/// Every cell the head has entered this run, in order, shown as faint dots
+/// once the run ends.
+#[derive(Resource, Default)]
+struct RunPath(Vec<Position>);
+
+/// Slow-motion lead-in and freeze frame for deaths. When the next move is
+/// fatal, that last tick plays at `DEATH_SLOW_MO_INTERVAL` with input locked,
+/// so the snake still dies on the same cell, then the board holds still for
+/// `DEATH_FREEZE_SECONDS` before respawning. F4.
+#[derive(Resource, Default)]
+struct DeathSlowMo {
+    enabled: bool,
+    /// The normal movement interval, while the lead-in has slowed it down.
+    lead_in: Option<Duration>,
+    freeze: Option<Timer>,
+}
+
+/// How the snake dies on screen. Any style other than `None` holds the board
+/// for the death freeze and plays over it. Set with `death_animation` in
+/// `SETTINGS_PATH`.
+#[derive(Resource, Default, Clone, Copy, PartialEq)]
+enum DeathAnimation {
+    #[default]
+    None,
+    /// Segments fly apart and fade.
+    Explode,
+    /// Segments fade one by one, tail first.
+    Dissolve,
+    /// The whole snake flashes.
+    Blink,
+    /// The snake slides on for `COAST_TICKS` cells through whatever it hit.
+    /// Only the drawing moves; the death was already decided at the crash.
+    Coast,
+}
+
+impl DeathAnimation {
+    fn from_settings(settings: &Settings) -> Result<Self, SnakeError> {
+        settings
+            .get(
+                "death_animation",
+                "none, explode, dissolve, blink or coast",
+                |value| match value {
+                    "none" => Some(Self::None),
+                    "explode" => Some(Self::Explode),
+                    "dissolve" => Some(Self::Dissolve),
+                    "blink" => Some(Self::Blink),
+                    "coast" => Some(Self::Coast),
+                    _ => None,
+                },
+            )
+            .map(Option::unwrap_or_default)
+    }
+}
+
+/// Per-run state that goes back to its defaults when the snake respawns.
+#[derive(SystemParam)]
+struct RunResources<'w> {
+    score: ResMut<'w, Score>,
+    last_registered: ResMut<'w, LastRegisteredDir>,
+    buffer: ResMut<'w, InputBuffer>,
+    turns: ResMut<'w, TurnsSinceEat>,
+    clock: ResMut<'w, RunClock>,
+    growth: ResMut<'w, PendingGrowth>,
+    grace: ResMut<'w, WallGrace>,
+    last_tail: ResMut<'w, LastTailPosition>,
+    path: ResMut<'w, RunPath>,
+    effect: ResMut<'w, ActiveEffect>,
+    shrinking: ResMut<'w, ShrinkingArena>,
+}
+
+impl RunResources<'_> {
+    fn reset(&mut self) {
+        self.score.0 = 0;
+        self.last_registered.0 = None;
+        self.buffer.0.clear();
+        self.turns.0 = None;
+        self.clock.0.reset();
+        self.growth.0.clear();
+        self.grace.holding = false;
+        self.last_tail.0 = None;
+        self.path.0.clear();
+        self.effect.0 = None;
+        self.shrinking.rings = 0;
+        if let Some(timer) = self.shrinking.timer.as_mut() {
+            timer.reset();
+        }
+    }
+}
+
+/// Everything needed to replay a board: the food seed and the settings that
+/// change where food and walls end up. Shared as a short code, see
+/// `encode_run`.
+#[derive(Resource, Clone, Copy, PartialEq)]
+struct RunParams {
+    seed: u32,
+    corpse_walls: bool,
+    trap_spawn: bool,
+    avoid_heading: bool,
+    tail_severing: bool,
+}
+
+impl Default for RunParams {
+    fn default() -> Self {
+        Self {
+            seed: rand::random(),
+            corpse_walls: false,
+            trap_spawn: false,
+            avoid_heading: false,
+            tail_severing: false,
+        }
+    }
+}
+
+/// Packs the params and the arena size into 48 bits, written in base 36.
+fn encode_run(params: &RunParams) -> String {
+    let flags = params.corpse_walls as u64
+        | (params.trap_spawn as u64) << 1
+        | (params.avoid_heading as u64) << 2
+        | (params.tail_severing as u64) << 3;
+    let mut packed =
+        params.seed as u64 | flags << 32 | (ARENA_WIDTH as u64) << 36 | (ARENA_HEIGHT as u64) << 42;
+
+    let radix = RUN_CODE_DIGITS.len() as u64;
+    let mut digits = Vec::new();
+    while packed > 0 {
+        digits.push(RUN_CODE_DIGITS[(packed % radix) as usize] as char);
+        packed /= radix;
+    }
+    digits.iter().rev().collect()
+}
+
+/// `None` for anything that isn't a code, or is a code for another arena size.
+fn decode_run(code: &str) -> Option<RunParams> {
+    let radix = RUN_CODE_DIGITS.len() as u64;
+    let mut packed: u64 = 0;
+    for c in code.trim().chars() {
+        let digit = RUN_CODE_DIGITS
+            .iter()
+            .position(|&d| d as char == c.to_ascii_uppercase())?;
+        packed = packed.checked_mul(radix)?.checked_add(digit as u64)?;
+    }
+
+    let width = (packed >> 36) & 0x3f;
+    let height = packed >> 42;
+    if width != ARENA_WIDTH as u64 || height != ARENA_HEIGHT as u64 {
+        return None;
+    }
+    let flags = packed >> 32;
+    Some(RunParams {
+        seed: packed as u32,
+        corpse_walls: flags & 1 != 0,
+        trap_spawn: flags & 2 != 0,
+        avoid_heading: flags & 4 != 0,
+        tail_severing: flags & 8 != 0,
+    })
+}
+
+/// Randomness for anything that has to replay from a run code.
+#[derive(Resource)]
+struct GameRng(StdRng);
+
+impl GameRng {
+    fn new(seed: u32) -> Self {
+        Self(StdRng::seed_from_u64(seed as u64))
+    }
+}
+
+/// The run code being typed after F7, and the one waiting to start.
+#[derive(Resource, Default)]
+struct CodeEntry {
+    typing: Option<String>,
+    pending: Option<RunParams>,
+    /// Every life replays the entered code until an empty code is entered.
+    pinned: bool,
+}
+
+#[derive(SystemParam)]
+struct RunCode<'w> {
+    params: ResMut<'w, RunParams>,
+    rng: ResMut<'w, GameRng>,
+    entry: ResMut<'w, CodeEntry>,
+    corpse_walls: ResMut<'w, CorpseWalls>,
+    trap_spawn: ResMut<'w, TrapSpawn>,
+    avoid_heading: ResMut<'w, FoodAvoidsHeading>,
+    severing: ResMut<'w, TailSevering>,
+}
+
+impl RunCode<'_> {
+    /// Sets up the run about to start and returns whether it replays a code
+    /// that was just entered. Otherwise a pinned code plays again, or a fresh
+    /// seed is rolled; either way with the current settings.
+    fn start_run(&mut self) -> bool {
+        let replay = self.entry.pending.take();
+        if let Some(params) = replay {
+            self.corpse_walls.0 = params.corpse_walls;
+            self.trap_spawn.0 = params.trap_spawn;
+            self.avoid_heading.0 = params.avoid_heading;
+            self.severing.0 = params.tail_severing;
+            self.params.seed = params.seed;
+        } else if !self.entry.pinned {
+            self.params.seed = rand::random();
+        }
+
+        self.params.corpse_walls = self.corpse_walls.0;
+        self.params.trap_spawn = self.trap_spawn.0;
+        self.params.avoid_heading = self.avoid_heading.0;
+        self.params.tail_severing = self.severing.0;
+        *self.rng = GameRng::new(self.params.seed);
+        replay.is_some()
+    }
+}
+
+/// Everything besides the body itself that decides what the head crashes into.
+#[derive(SystemParam)]
+struct CollisionRules<'w, 's> {
+    severing: Res<'w, TailSevering>,
+    grace: ResMut<'w, WallGrace>,
+    toggles: Res<'w, SystemToggles>,
+    pending_growth: Res<'w, PendingGrowth>,
+    effect: Res<'w, ActiveEffect>,
+    style: Res<'w, MovementStyle>,
+    walls: Query<'w, 's, &'static Position, With<Wall>>,
+}
+
+/// Everything that decides where and how the snake respawns.
+#[derive(SystemParam)]
+struct SpawnSettings<'w> {
+    start: Res<'w, Start>,
+    policy: Res<'w, RespawnPolicy>,
+    protection: Res<'w, SpawnProtection>,
+}
+
+impl SpawnSettings<'_> {
+    fn respawn_cell(&self, died_at: Option<Position>) -> Position {
+        let configured = self.start.cell.unwrap_or_else(arena_center);
+        match *self.policy {
+            RespawnPolicy::Start => configured,
+            RespawnPolicy::NearDeath => died_at.map_or(configured, safe_respawn_cell),
+        }
+    }
+}
+
+/// Turns committed since the last apple; `None` until the first one is eaten.
+/// Eating again without turning earns `STRAIGHT_SHOT_BONUS`.
+#[derive(Resource, Default)]
+struct TurnsSinceEat(Option<u32>);
+
+/// Set by the dash key; the next movement tick advances the head two cells.
+#[derive(Resource, Default)]
+pub struct DashPending(pub bool);
+
+/// The cell a dash skipped over this tick, so food there is still eaten.
+/// `snake_movement` clears it every tick, so it never outlives its tick even
+/// when `snake_eating` doesn't run.
+#[derive(Resource, Default)]
+struct DashedOver(Option<Position>);
+
+/// When enabled, biting your own body cuts the snake at the bite instead of
+/// ending the run. Set with `tail_severing=on` in `SETTINGS_PATH`.
+#[derive(Resource, Default)]
+struct TailSevering(bool);
+
+impl TailSevering {
+    fn from_settings(settings: &Settings) -> Result<Self, SnakeError> {
+        settings.flag("tail_severing").map(Self)
+    }
+}
+
+/// Beginner help: a move into a wall holds the snake at the edge for one tick,
+/// flashing, and only kills it if the player doesn't turn away in time. Set
+/// with `wall_grace=on` in `SETTINGS_PATH`.
+#[derive(Resource, Default)]
+struct WallGrace {
+    enabled: bool,
+    holding: bool,
+}
+
+impl WallGrace {
+    fn from_settings(settings: &Settings) -> Result<Self, SnakeError> {
+        settings.flag("wall_grace").map(|enabled| Self {
+            enabled,
+            holding: false,
+        })
+    }
+}
+
+/// When enabled, a new segment appears a tick after eating instead of on the
+/// eat tick itself. Set with `grow_delay=on` in `SETTINGS_PATH`.
+#[derive(Resource, Default)]
+struct GrowDelay(bool);
+
+impl GrowDelay {
+    fn from_settings(settings: &Settings) -> Result<Self, SnakeError> {
+        settings.flag("grow_delay").map(Self)
+    }
+}
+
+/// Ticks on which growth was reserved but its segment not yet spawned. The
+/// reserved cell is still part of the body for collisions.
+#[derive(Resource, Default)]
+struct PendingGrowth(Vec<u64>);
+
+#[derive(Resource)]
+struct MovementTimer(Timer);
+
+/// Number of movement ticks since the game started.
+#[derive(Resource, Default)]
+struct TickCount(u64);
+
+impl Default for MovementTimer {
+    fn default() -> Self {
+        Self(Timer::from_seconds(MOVEMENT_INTERVAL, TimerMode::Repeating))
+    }
+}
+
+/// Classic mode: the snake always moves every `interval` seconds, and no
+/// speed feature may change that. Set with `constant_speed=on` and
+/// `constant_speed_interval=<seconds>` in `SETTINGS_PATH`.
+#[derive(Resource)]
+struct ConstantSpeed {
+    enabled: bool,
+    interval: f32,
+}
+
+impl Default for ConstantSpeed {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval: MOVEMENT_INTERVAL,
+        }
+    }
+}
+
+impl ConstantSpeed {
+    fn from_settings(settings: &Settings) -> Result<Self, SnakeError> {
+        let enabled = settings.flag("constant_speed")?;
+        let interval = settings.get(
+            "constant_speed_interval",
+            "a number of seconds from 0.05 to 1",
+            |value| {
+                value
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|interval| (0.05..=1.0).contains(interval))
+            },
+        )?;
+        Ok(Self {
+            enabled,
+            interval: interval.unwrap_or(MOVEMENT_INTERVAL),
+        })
+    }
+}
+
+/// Adaptive difficulty: the movement interval follows how long recent lives
+/// lasted, between `AUTO_BALANCE_SLOWEST` and `AUTO_BALANCE_FASTEST`. Set with
+/// `auto_balance=on` in `SETTINGS_PATH`.
+#[derive(Resource, Default)]
+struct AutoBalance(bool);
+
+impl AutoBalance {
+    fn from_settings(settings: &Settings) -> Result<Self, SnakeError> {
+        settings.flag("auto_balance").map(Self)
+    }
+}
+
+/// Ticks survived by the last `AUTO_BALANCE_LIVES` lives.
+#[derive(Resource, Default)]
+struct LifeStats {
+    recent: VecDeque<u64>,
+    life_start: u64,
+}
+
+impl LifeStats {
+    fn end_life(&mut self, tick: u64) {
+        if self.recent.len() == AUTO_BALANCE_LIVES {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(tick - self.life_start);
+        self.life_start = tick;
+    }
+
+    /// Mean ticks per life, counting the life in progress so a long run speeds
+    /// up as it goes and a death eases straight back.
+    fn mean(&self, tick: u64) -> f32 {
+        let total: u64 = self.recent.iter().sum::<u64>() + (tick - self.life_start);
+        total as f32 / (self.recent.len() + 1) as f32
+    }
+}
+
+/// Challenge where food comes in two colors and only the target color grows
+/// the snake; eating the other one costs a point. The target flips every
+/// `TARGET_COLOR_PERIOD` seconds. F3.
+#[derive(Resource)]
+struct ColorChallenge {
+    enabled: bool,
+    target: FoodColor,
+    timer: Timer,
+}
+
+impl Default for ColorChallenge {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            target: FoodColor::Magenta,
+            timer: Timer::from_seconds(TARGET_COLOR_PERIOD, TimerMode::Repeating),
+        }
+    }
+}
+
+/// Shows the held arrow keys in a corner, for streaming and teaching. F2.
+#[derive(Resource, Default)]
+struct InputVisualizer(bool);
+
+/// Shifts the snake toward `SPEED_TINT_COLOR` as the movement interval drops
+/// below `MOVEMENT_INTERVAL`. Set with `speed_tint=on` in `SETTINGS_PATH`.
+#[derive(Resource, Default)]
+struct SpeedTint(bool);
+
+impl SpeedTint {
+    fn from_settings(settings: &Settings) -> Result<Self, SnakeError> {
+        settings.flag("speed_tint").map(Self)
+    }
+}
+
+/// Shows the head's current direction in a corner, toggled with F9 and saved
+/// to `SETTINGS_PATH`.
+#[derive(Resource, Default)]
+struct Compass(bool);
+
+impl Compass {
+    fn from_settings(settings: &Settings) -> Result<Self, SnakeError> {
+        settings.flag("compass").map(Self)
+    }
+}
+
+/// Outlines the cell the head moves into on the next tick. Set with
+/// `next_cell=on` in `SETTINGS_PATH`.
+#[derive(Resource, Default)]
+struct ShowNextCell(bool);
+
+impl ShowNextCell {
+    fn from_settings(settings: &Settings) -> Result<Self, SnakeError> {
+        settings.flag("next_cell").map(Self)
+    }
+}
+
+/// How the arrow keys steer. `Relative` only uses left and right, each press
+/// turning the snake a quarter turn from its heading. Set with
+/// `controls=relative` in `SETTINGS_PATH`.
+#[derive(Resource, Default, Clone, Copy, PartialEq)]
+enum ControlScheme {
+    #[default]
+    Absolute,
+    Relative,
+}
+
+impl ControlScheme {
+    fn from_settings(settings: &Settings) -> Result<Self, SnakeError> {
+        settings
+            .get("controls", "absolute or relative", |value| match value {
+                "absolute" => Some(Self::Absolute),
+                "relative" => Some(Self::Relative),
+                _ => None,
+            })
+            .map(Option::unwrap_or_default)
+    }
+}
+
+/// What counts as steering with the absolute arrows. `Held` samples a key on
+/// every frame it is down, but `LastRegisteredDir` keeps it from queuing again
+/// until the sampled direction changes or every arrow is released, and a held
+/// arrow hides the ones below it (left, right, down, up in that order); `Tap`
+/// only counts the frame a key went down, so one press is one turn.
+/// Set with `key_trigger=held` or `key_trigger=tap` in `SETTINGS_PATH`.
+/// Relative controls always turn on a fresh press.
+#[derive(Resource, Default, Clone, Copy, PartialEq)]
+enum KeyTrigger {
+    #[default]
+    Held,
+    Tap,
+}
+
+impl KeyTrigger {
+    fn from_settings(settings: &Settings) -> Result<Self, SnakeError> {
+        settings
+            .get("key_trigger", "held or tap", |value| match value {
+                "held" => Some(Self::Held),
+                "tap" => Some(Self::Tap),
+                _ => None,
+            })
+            .map(Option::unwrap_or_default)
+    }
+}
+
+/// Camera shake on death: the largest offset in pixels and how long it lasts
+/// in seconds. Either at zero turns it off. F6 cycles the intensity through
+/// full, half and off, saved to `SETTINGS_PATH`.
+#[derive(Resource)]
+struct ScreenShake {
+    intensity: f32,
+    duration: f32,
+}
+
+impl Default for ScreenShake {
+    fn default() -> Self {
+        Self {
+            intensity: SHAKE_INTENSITY,
+            duration: SHAKE_SECONDS,
+        }
+    }
+}
+
+impl ScreenShake {
+    fn from_settings(settings: &Settings) -> Result<Self, SnakeError> {
+        let amount = |value: &str| value.parse::<f32>().ok().filter(|&v| v >= 0.0);
+        let defaults = Self::default();
+        Ok(Self {
+            intensity: settings
+                .get("shake_intensity", "a number of pixels, 0 or more", amount)?
+                .unwrap_or(defaults.intensity),
+            duration: settings
+                .get("shake_duration", "a number of seconds, 0 or more", amount)?
+                .unwrap_or(defaults.duration),
+        })
+    }
+
+    fn enabled(&self) -> bool {
+        self.intensity > 0.0 && self.duration > 0.0
+    }
+}
+
+/// Flips every arrow to its opposite, for a challenge. F10 toggles it, saved
+/// to `SETTINGS_PATH`, and the HUD says so while it is on.
+#[derive(Resource, Default)]
+struct InvertControls(bool);
+
+impl InvertControls {
+    fn from_settings(settings: &Settings) -> Result<Self, SnakeError> {
+        settings.flag("invert_controls").map(Self)
+    }
+}
+
+/// How the body follows the head. In `Tron` the snake never grows: every
+/// cell its tail leaves becomes a wall for the rest of the run, and food is
+/// off. Set with `movement=tron` in `SETTINGS_PATH`.
+#[derive(Resource, Default, Clone, Copy, PartialEq)]
+enum MovementStyle {
+    #[default]
+    Classic,
+    Tron,
+}
+
+impl MovementStyle {
+    fn from_settings(settings: &Settings) -> Result<Self, SnakeError> {
+        settings
+            .get("movement", "classic or tron", |value| match value {
+                "classic" => Some(Self::Classic),
+                "tron" => Some(Self::Tron),
+                _ => None,
+            })
+            .map(Option::unwrap_or_default)
+    }
+}
+
+/// Presets over the individual toggles, picked with `mode` in
+/// `SETTINGS_PATH`. A mode is applied after the toggles' own keys and wins
+/// where they overlap; any knob it doesn't set keeps its key, so `mode=zen`
+/// with `corpse_walls=on` is zen on a board of corpses.
+#[derive(Default, Clone, Copy, PartialEq)]
+enum GameMode {
+    #[default]
+    Classic,
+    /// Forgiving: wall grace, severing instead of self-collision deaths, no
+    /// food in the snake's path, and a slow fixed speed.
+    Zen,
+    /// Dead snakes become walls, at a fast fixed speed.
+    Hardcore,
+    /// Every run ends after `TIME_ATTACK_SECONDS`, unless
+    /// `SNAKE_MAX_RUN_SECONDS` already sets a limit.
+    TimeAttack,
+    /// Dead snakes become walls and food hides in corners and along edges,
+    /// so the board closes into a maze over a session.
+    Maze,
+}
+
+impl GameMode {
+    fn from_settings(settings: &Settings) -> Result<Self, SnakeError> {
+        settings
+            .get(
+                "mode",
+                "classic, zen, hardcore, time_attack or maze",
+                |value| match value {
+                    "classic" => Some(Self::Classic),
+                    "zen" => Some(Self::Zen),
+                    "hardcore" => Some(Self::Hardcore),
+                    "time_attack" => Some(Self::TimeAttack),
+                    "maze" => Some(Self::Maze),
+                    _ => None,
+                },
+            )
+            .map(Option::unwrap_or_default)
+    }
+
+    fn apply(self, world: &mut World) {
+        match self {
+            Self::Classic => {}
+            Self::Zen => {
+                world.resource_mut::<WallGrace>().enabled = true;
+                world.resource_mut::<TailSevering>().0 = true;
+                world.resource_mut::<FoodAvoidsHeading>().0 = true;
+                *world.resource_mut::<ConstantSpeed>() = ConstantSpeed {
+                    enabled: true,
+                    interval: ZEN_INTERVAL,
+                };
+            }
+            Self::Hardcore => {
+                world.resource_mut::<CorpseWalls>().0 = true;
+                *world.resource_mut::<ConstantSpeed>() = ConstantSpeed {
+                    enabled: true,
+                    interval: HARDCORE_INTERVAL,
+                };
+            }
+            Self::TimeAttack => {
+                let mut limit = world.resource_mut::<MaxRunDuration>();
+                if limit.0.is_none() {
+                    limit.0 = Some(Duration::from_secs(TIME_ATTACK_SECONDS));
+                }
+            }
+            Self::Maze => {
+                world.resource_mut::<CorpseWalls>().0 = true;
+                world.resource_mut::<TrapSpawn>().0 = true;
+            }
+        }
+    }
+}
+
+/// Battle-royale pressure: every interval the outermost open ring of the
+/// arena turns to wall, until the snake runs out of room. Set in seconds with
+/// `shrink_seconds` in `SETTINGS_PATH`; off by default.
+#[derive(Resource, Default)]
+struct ShrinkingArena {
+    timer: Option<Timer>,
+    /// Rings walled off so far this run.
+    rings: i32,
+}
+
+impl ShrinkingArena {
+    fn from_settings(settings: &Settings) -> Result<Self, SnakeError> {
+        let interval = settings.get("shrink_seconds", "a number of seconds above 0", |value| {
+            value.parse::<f32>().ok().filter(|&secs| secs > 0.0)
+        })?;
+        Ok(Self {
+            timer: interval.map(|secs| Timer::from_seconds(secs, TimerMode::Repeating)),
+            rings: 0,
+        })
+    }
+}
+
+/// Hunting mode: every `PREY_STEP_TICKS` food steps one cell away from the
+/// head, if a free cell lets it. Set with `prey_food=on` in `SETTINGS_PATH`.
+#[derive(Resource, Default)]
+struct MovingFood(bool);
+
+impl MovingFood {
+    fn from_settings(settings: &Settings) -> Result<Self, SnakeError> {
+        settings.flag("prey_food").map(Self)
+    }
+}
+
+/// Chaos mode: food sometimes spawns as `SplitterFood`, which leaves two more
+/// behind when eaten. Set with `splitter_food=on` in `SETTINGS_PATH`.
+#[derive(Resource, Default)]
+struct SplittingFood(bool);
+
+impl SplittingFood {
+    fn from_settings(settings: &Settings) -> Result<Self, SnakeError> {
+        settings.flag("splitter_food").map(Self)
+    }
+}
+
+/// Beginner assist: tints the largest open region the head can still move
+/// into, so dead ends show up before the snake enters them. Set with
+/// `safe_path=on` in `SETTINGS_PATH`.
+#[derive(Resource, Default)]
+struct SafePathHint(bool);
+
+impl SafePathHint {
+    fn from_settings(settings: &Settings) -> Result<Self, SnakeError> {
+        settings.flag("safe_path").map(Self)
+    }
+}
+
+/// Draws a streak behind the head once it moves faster than
+/// `MOTION_BLUR_MIN_SPEED` cells a second. On unless `motion_blur=off` in
+/// `SETTINGS_PATH`.
+#[derive(Resource)]
+struct MotionBlur(bool);
+
+impl Default for MotionBlur {
+    fn default() -> Self {
+        Self(true)
+    }
+}
+
+impl MotionBlur {
+    fn from_settings(settings: &Settings) -> Result<Self, SnakeError> {
+        settings.flag_or("motion_blur", true).map(Self)
+    }
+}
+
+/// Sideways sway of body segments, in tiles. Zero turns it off. Set with
+/// `wiggle` in `SETTINGS_PATH`.
+#[derive(Resource)]
+struct Wiggle(f32);
+
+impl Default for Wiggle {
+    fn default() -> Self {
+        Self(WIGGLE_AMPLITUDE)
+    }
+}
+
+impl Wiggle {
+    fn from_settings(settings: &Settings) -> Result<Self, SnakeError> {
+        settings
+            .get("wiggle", "a number of tiles from 0 to 0.5", |value| {
+                value
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|amplitude| (0.0..=0.5).contains(amplitude))
+            })
+            .map(|amplitude| Self(amplitude.unwrap_or(WIGGLE_AMPLITUDE)))
+    }
+}
+
+/// How an eaten apple is scored, read from the `scoring` setting. `Length`
+/// adds the snake's current length to the apple's own value.
+#[derive(Resource, Default, Clone, Copy, PartialEq)]
+enum ScoringMode {
+    #[default]
+    Flat,
+    Length,
+}
+
+impl ScoringMode {
+    fn from_settings(settings: &Settings) -> Result<Self, SnakeError> {
+        settings
+            .get("scoring", "flat or length", |value| match value {
+                "flat" => Some(Self::Flat),
+                "length" => Some(Self::Length),
+                _ => None,
+            })
+            .map(Option::unwrap_or_default)
+    }
+
+    fn award(self, value: u32, length: usize) -> u32 {
+        match self {
+            Self::Flat => value,
+            Self::Length => value + length as u32,
+        }
+    }
+}
+
+/// Extra points per apple already in the current streak, so the fourth apple
+/// in a row pays three times the bonus. Set with `streak_bonus=<points>` in
+/// `SETTINGS_PATH`; 0, the default, turns it off.
+#[derive(Resource, Default)]
+struct StreakBonus(u32);
+
+impl StreakBonus {
+    fn from_settings(settings: &Settings) -> Result<Self, SnakeError> {
+        settings
+            .get(
+                "streak_bonus",
+                "a whole number of points from 0 to 100",
+                |value| value.parse::<u32>().ok().filter(|points| *points <= 100),
+            )
+            .map(|points| Self(points.unwrap_or(0)))
+    }
+}
+
+/// How eaten food is scored: the scoring mode plus the streak bonus.
+#[derive(SystemParam)]
+struct Scoring<'w> {
+    mode: Res<'w, ScoringMode>,
+    streak: Res<'w, Streak>,
+    streak_bonus: Res<'w, StreakBonus>,
+}
+
+/// How far each body segment is drawn toward the one ahead of it, in tiles,
+/// for a "beads on a string" look. Purely cosmetic: `Position` is untouched.
+#[derive(Resource, Default)]
+struct SegmentSpacing(f32);
+
+impl SegmentSpacing {
+    fn from_settings(settings: &Settings) -> Result<Self, SnakeError> {
+        settings
+            .get(
+                "segment_spacing",
+                "a number of tiles from 0 to 0.5",
+                |value| {
+                    value
+                        .parse::<f32>()
+                        .ok()
+                        .filter(|spacing| (0.0..=0.5).contains(spacing))
+                },
+            )
+            .map(|spacing| Self(spacing.unwrap_or(0.0)))
+    }
+}
+
+/// Overall look of the board, switched with F5 and saved to `SETTINGS_PATH`.
+/// `Nokia` is flat dark pixels on a green LCD, with sprites, tint, blur and
+/// popup animation all off.
+#[derive(Resource, Default, Clone, Copy, PartialEq)]
+enum Theme {
+    #[default]
+    Classic,
+    Nokia,
+}
+
+impl Theme {
+    fn from_settings(settings: &Settings) -> Result<Self, SnakeError> {
+        settings
+            .get("theme", "classic or nokia", |value| match value {
+                "classic" => Some(Self::Classic),
+                "nokia" => Some(Self::Nokia),
+                _ => None,
+            })
+            .map(Option::unwrap_or_default)
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Classic => "classic",
+            Self::Nokia => "nokia",
+        }
+    }
+
+    fn animated(self) -> bool {
+        self == Self::Classic
+    }
+
+    fn background(self) -> Color {
+        match self {
+            Self::Classic => ClearColor::default().0,
+            Self::Nokia => NOKIA_LCD_COLOR,
+        }
+    }
+
+    /// Top and bottom colors of the background gradient.
+    fn gradient(self) -> [Color; 2] {
+        match self {
+            Self::Classic => [BACKGROUND_TOP_COLOR, BACKGROUND_BOTTOM_COLOR],
+            Self::Nokia => [NOKIA_LCD_COLOR, NOKIA_LCD_COLOR],
+        }
+    }
+
+    fn head(self) -> Color {
+        match self {
+            Self::Classic => SNAKE_HEAD_COLOR,
+            Self::Nokia => NOKIA_PIXEL_COLOR,
+        }
+    }
+
+    fn segment(self) -> Color {
+        match self {
+            Self::Classic => SNAKE_SEGMENT_COLOR,
+            Self::Nokia => NOKIA_PIXEL_COLOR,
+        }
+    }
+
+    fn food(self, food: Color) -> Color {
+        match self {
+            Self::Classic => food,
+            Self::Nokia => NOKIA_PIXEL_COLOR,
+        }
+    }
+
+    fn wall(self) -> Color {
+        match self {
+            Self::Classic => WALL_COLOR,
+            Self::Nokia => NOKIA_PIXEL_COLOR,
+        }
+    }
+}
+
+/// Why a file read at startup could not be used. `load_config` logs these and
+/// falls back to defaults.
+#[derive(Debug)]
+enum SnakeError {
+    Io {
+        path: &'static str,
+        source: std::io::Error,
+    },
+    Parse {
+        path: &'static str,
+        line: usize,
+        message: String,
+    },
+    Invalid {
+        key: String,
+        value: String,
+        expected: &'static str,
+    },
+}
+
+impl fmt::Display for SnakeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io { path, source } => write!(f, "cannot read {path}: {source}"),
+            Self::Parse {
+                path,
+                line,
+                message,
+            } => write!(f, "{path}:{line}: {message}"),
+            Self::Invalid {
+                key,
+                value,
+                expected,
+            } => write!(f, "{SETTINGS_PATH}: {key}={value} should be {expected}"),
+        }
+    }
+}
+
+impl std::error::Error for SnakeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Reads `path`, treating a missing file as `None` rather than an error.
+fn read_optional(path: &'static str) -> Result<Option<String>, SnakeError> {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(source) => Err(SnakeError::Io { path, source }),
+    }
+}
+
+/// The `key=value` lines of `SETTINGS_PATH`. Blank lines and `#` comments are
+/// skipped.
+#[derive(Default)]
+struct Settings(HashMap<String, String>);
+
+impl Settings {
+    fn load() -> Result<Self, SnakeError> {
+        let Some(contents) = read_optional(SETTINGS_PATH)? else {
+            return Ok(Self::default());
+        };
+        let mut settings = HashMap::new();
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(SnakeError::Parse {
+                    path: SETTINGS_PATH,
+                    line: i + 1,
+                    message: format!("expected key=value, found {line:?}"),
+                });
+            };
+            settings.insert(key.trim().to_string(), value.trim().to_string());
+        }
+        Ok(Self(settings))
+    }
+
+    /// Parses `key` if it is set. A value `parse` rejects is an error
+    /// describing what was `expected`.
+    fn get<T>(
+        &self,
+        key: &str,
+        expected: &'static str,
+        parse: impl FnOnce(&str) -> Option<T>,
+    ) -> Result<Option<T>, SnakeError> {
+        let Some(value) = self.0.get(key) else {
+            return Ok(None);
+        };
+        parse(value).map(Some).ok_or_else(|| SnakeError::Invalid {
+            key: key.to_string(),
+            value: value.clone(),
+            expected,
+        })
+    }
+
+    /// Reads an `on`/`off` switch, off when `key` isn't set.
+    fn flag(&self, key: &str) -> Result<bool, SnakeError> {
+        self.flag_or(key, false)
+    }
+
+    /// Reads an `on`/`off` switch, `default` when `key` isn't set.
+    fn flag_or(&self, key: &str, default: bool) -> Result<bool, SnakeError> {
+        self.get(key, "on or off", |value| match value {
+            "on" => Some(true),
+            "off" => Some(false),
+            _ => None,
+        })
+        .map(|on| on.unwrap_or(default))
+    }
+}
+
+/// Loads the settings and profile into resources. Any loader error is logged
+/// and only the resource it affects falls back to its default.
+fn load_config(world: &mut World) {
+    let settings = or_default(Settings::load());
+    world.insert_resource(or_default(Start::from_settings(&settings)));
+    world.insert_resource(or_default(RespawnPolicy::from_settings(&settings)));
+    world.insert_resource(or_default(SegmentSpacing::from_settings(&settings)));
+    world.insert_resource(or_default(Wiggle::from_settings(&settings)));
+    world.insert_resource(or_default(ScoringMode::from_settings(&settings)));
+    world.insert_resource(or_default(GrowDelay::from_settings(&settings)));
+    world.insert_resource(or_default(EatRadius::from_settings(&settings)));
+    world.insert_resource(or_default(AutoBalance::from_settings(&settings)));
+    world.insert_resource(or_default(FoodTierWeights::from_settings(&settings)));
+    world.insert_resource(or_default(FoodAvoidsHeading::from_settings(&settings)));
+    world.insert_resource(or_default(Theme::from_settings(&settings)));
+    world.insert_resource(or_default(SpeedTint::from_settings(&settings)));
+    world.insert_resource(or_default(MotionBlur::from_settings(&settings)));
+    world.insert_resource(or_default(Compass::from_settings(&settings)));
+    world.insert_resource(or_default(SafePathHint::from_settings(&settings)));
+    world.insert_resource(or_default(ShowNextCell::from_settings(&settings)));
+    world.insert_resource(or_default(ControlScheme::from_settings(&settings)));
+    world.insert_resource(or_default(KeyTrigger::from_settings(&settings)));
+    world.insert_resource(or_default(DeathAnimation::from_settings(&settings)));
+    world.insert_resource(or_default(MovingFood::from_settings(&settings)));
+    world.insert_resource(or_default(SplittingFood::from_settings(&settings)));
+    world.insert_resource(or_default(PowerUps::from_settings(&settings)));
+    world.insert_resource(or_default(ScreenShake::from_settings(&settings)));
+    world.insert_resource(or_default(InvertControls::from_settings(&settings)));
+    world.insert_resource(or_default(SpawnProtection::from_settings(&settings)));
+    world.insert_resource(or_default(MovementStyle::from_settings(&settings)));
+    world.insert_resource(or_default(ShrinkingArena::from_settings(&settings)));
+    world.insert_resource(or_default(TailSevering::from_settings(&settings)));
+    world.insert_resource(or_default(TrapSpawn::from_settings(&settings)));
+    world.insert_resource(or_default(CorpseWalls::from_settings(&settings)));
+    world.insert_resource(or_default(WallGrace::from_settings(&settings)));
+    world.insert_resource(or_default(ConstantSpeed::from_settings(&settings)));
+    world.insert_resource(or_default(StreakBonus::from_settings(&settings)));
+    world.insert_resource(or_default(Profile::load()));
+    or_default(GameMode::from_settings(&settings)).apply(world);
+}
+
+fn or_default<T: Default>(loaded: Result<T, SnakeError>) -> T {
+    loaded.unwrap_or_else(|err| {
+        warn!("{err}; using the default");
+        T::default()
+    })
+}
+
+/// Stores `key` in `SETTINGS_PATH`, leaving every other line as it was.
+fn write_setting(key: &str, value: &str) {
+    let contents = std::fs::read_to_string(SETTINGS_PATH).unwrap_or_default();
+    let mut lines: Vec<&str> = contents
+        .lines()
+        .filter(|line| line.split_once('=').map_or(true, |(k, _)| k.trim() != key))
+        .collect();
+    let setting = format!("{key}={value}");
+    lines.push(&setting);
+    if let Err(err) = std::fs::write(SETTINGS_PATH, lines.join("\n") + "\n") {
+        warn!("cannot save {SETTINGS_PATH}: {err}");
+    }
+}
+
+/// When enabled, food never spawns in the `FOOD_HEADING_GUARD` cells right in
+/// front of the head, so a new apple can't land in the snake's mouth. Set with
+/// `food_avoids_heading=on` in `SETTINGS_PATH`.
+#[derive(Resource, Default)]
+struct FoodAvoidsHeading(bool);
+
+impl FoodAvoidsHeading {
+    fn from_settings(settings: &Settings) -> Result<Self, SnakeError> {
+        settings.flag("food_avoids_heading").map(Self)
+    }
+}
+
+/// Roguelike mode: a dead snake's body stays on the board as walls for the
+/// rest of the session, until there is no room left to start a new one. Set
+/// with `corpse_walls=on` in `SETTINGS_PATH`.
+#[derive(Resource, Default)]
+struct CorpseWalls(bool);
+
+impl CorpseWalls {
+    fn from_settings(settings: &Settings) -> Result<Self, SnakeError> {
+        settings.flag("corpse_walls").map(Self)
+    }
+}
+
+/// Practice mode: food favours corners, edges and the cells around the tail,
+/// forcing tight maneuvers. Set with `trap_spawn=on` in `SETTINGS_PATH`.
+#[derive(Resource, Default)]
+struct TrapSpawn(bool);
+
+impl TrapSpawn {
+    fn from_settings(settings: &Settings) -> Result<Self, SnakeError> {
+        settings.flag("trap_spawn").map(Self)
+    }
+}
+
+/// Everything gameplay announces to the rest of the game.
+///
+/// Producers send a variant; consumers read `GameEvent` and match on the
+/// variants they react to. A new reaction (a sound, a particle, a HUD flash)
+/// is one more match arm in a consumer instead of a new event type plumbed
+/// through `main`.
+#[derive(Event, Clone, Copy, PartialEq)]
+enum GameEvent {
+    Growth,
+    /// Food was eaten for points, whatever it grows the snake by.
+    Ate,
+    /// A `SplitterFood` was eaten at this cell.
+    Split(Position),
+    GameOver,
+}
+
+/// What eating this food is worth: `value` points and `grow` new segments.
+#[derive(Component)]
+struct Food {
+    value: u32,
+    grow: u32,
+}
+
+/// Rarity of a food, shown by its color unless the color challenge is on.
+#[derive(Component, Clone, Copy, PartialEq)]
+enum FoodTier {
+    Common,
+    Uncommon,
+    Rare,
+}
+
+impl FoodTier {
+    const ALL: [Self; 3] = [Self::Common, Self::Uncommon, Self::Rare];
+
+    fn food(self) -> Food {
+        let (value, grow) = match self {
+            Self::Common => (1, 1),
+            Self::Uncommon => (3, 2),
+            Self::Rare => (5, 3),
+        };
+        Food { value, grow }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            Self::Common => FOOD_COMMON_COLOR,
+            Self::Uncommon => FOOD_UNCOMMON_COLOR,
+            Self::Rare => FOOD_RARE_COLOR,
+        }
+    }
+}
+
+/// Relative spawn chances of each `FoodTier`. Set with
+/// `food_weights=common,uncommon,rare` in `SETTINGS_PATH`, e.g. `80,15,5`.
+#[derive(Resource)]
+struct FoodTierWeights {
+    common: u32,
+    uncommon: u32,
+    rare: u32,
+}
+
+impl Default for FoodTierWeights {
+    fn default() -> Self {
+        Self {
+            common: FOOD_COMMON_WEIGHT,
+            uncommon: FOOD_UNCOMMON_WEIGHT,
+            rare: FOOD_RARE_WEIGHT,
+        }
+    }
+}
+
+impl FoodTierWeights {
+    /// At least one weight has to be above zero, or no tier could spawn.
+    fn from_settings(settings: &Settings) -> Result<Self, SnakeError> {
+        settings
+            .get(
+                "food_weights",
+                "three whole numbers like 80,15,5, not all 0",
+                |value| {
+                    let weights: Vec<u32> = value
+                        .split(',')
+                        .map(|weight| weight.trim().parse().ok())
+                        .collect::<Option<_>>()?;
+                    let &[common, uncommon, rare] = weights.as_slice() else {
+                        return None;
+                    };
+                    weights.iter().any(|&weight| weight > 0).then_some(Self {
+                        common,
+                        uncommon,
+                        rare,
+                    })
+                },
+            )
+            .map(Option::unwrap_or_default)
+    }
+
+    fn pick(&self, rng: &mut impl Rng) -> FoodTier {
+        *FoodTier::ALL
+            .choose_weighted(rng, |&tier| self.weight(tier))
+            .unwrap_or(&FoodTier::Common)
+    }
+
+    fn weight(&self, tier: FoodTier) -> u32 {
+        match tier {
+            FoodTier::Common => self.common,
+            FoodTier::Uncommon => self.uncommon,
+            FoodTier::Rare => self.rare,
+        }
+    }
+}
+
+/// Casual mode: food within this many cells (Manhattan) of the head is eaten.
+/// Zero means the head has to land on it. Set with `eat_radius` in
+/// `SETTINGS_PATH`, up to `MAX_EAT_RADIUS`.
+#[derive(Resource, Default)]
+struct EatRadius(u32);
+
+impl EatRadius {
+    fn from_settings(settings: &Settings) -> Result<Self, SnakeError> {
+        settings
+            .get(
+                "eat_radius",
+                "a whole number of cells from 0 to 3",
+                |value| {
+                    value
+                        .parse::<u32>()
+                        .ok()
+                        .filter(|&radius| radius <= MAX_EAT_RADIUS)
+                },
+            )
+            .map(|radius| Self(radius.unwrap_or(0)))
+    }
+}
+
+/// Whether food is in reach of the head, given the eat radius and any power-up
+/// that widens the head.
+#[derive(SystemParam)]
+struct EatReach<'w> {
+    radius: Res<'w, EatRadius>,
+    effect: Res<'w, ActiveEffect>,
+}
+
+impl EatReach<'_> {
+    fn reaches(&self, head: Position, heading: Direction, food: Position) -> bool {
+        self.effect
+            .head_cells(head, heading)
+            .iter()
+            .any(|cell| cell.manhattan(food) <= self.radius.0)
+    }
+}
+
+/// A drawn-only copy of food eaten from a distance. It slides the given
+/// offset, in tiles, onto the head over its `Lifetime`.
+#[derive(Component)]
+struct FoodPull(Vec2);
+
+/// Rare food that is only visible, and only edible, on even ticks.
+#[derive(Component)]
+struct FlickerFood;
+
+/// Rare food, drawn smaller, that splits into two common foods when eaten.
+#[derive(Component)]
+struct SplitterFood;
+
+#[derive(Component, Clone, Copy, PartialEq)]
+enum FoodColor {
+    Magenta,
+    Cyan,
+}
+
+impl FoodColor {
+    fn color(self) -> Color {
+        match self {
+            Self::Magenta => FOOD_COLOR,
+            Self::Cyan => FOOD_ALT_COLOR,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Magenta => "magenta",
+            Self::Cyan => "cyan",
+        }
+    }
+
+    fn other(self) -> Self {
+        match self {
+            Self::Magenta => Self::Cyan,
+            Self::Cyan => Self::Magenta,
+        }
+    }
+}
+
+#[derive(Component)]
+struct HudText;
+
+#[derive(Component)]
+struct RunCodeText;
+
+#[derive(Component)]
+struct CompassText;
+
+#[derive(Component)]
+struct InvertedText;
+
+/// Lifetime stats from the `Profile`, toggled with F8.
+#[derive(Component)]
+struct StatsPanel;
+
+/// Lists every food on the board under `SNAKE_DEBUG`.
+#[derive(Component)]
+struct FoodList;
+
+#[derive(Component)]
+struct GoalProgressRoot;
+
+#[derive(Component)]
+struct GoalProgressFill;
+
+#[derive(Component)]
+struct WinScreen;
+
+#[derive(Component)]
+struct BuriedScreen;
+
+/// Countdown shown in the last seconds before a kiosk reset.
+#[derive(Component)]
+struct KioskCountdown;
+
+/// Floating text that rises and fades out over its `Lifetime`.
+#[derive(Component)]
+struct Popup;
+
+/// Dot on a cell the last run's head passed through, fading out over its
+/// `Lifetime` after the respawn.
+#[derive(Component)]
+struct PathMarker;
+
+/// Despawns its entity when the timer runs out. Every short-lived effect uses
+/// this rather than its own timer, so cleanup lives in `despawn_expired`.
+#[derive(Component)]
+struct Lifetime(Timer);
+
+/// Cap on live `Lifetime` effects, so a busy moment can't flood the world with
+/// short-lived entities. Set with `SNAKE_MAX_EFFECTS`.
+#[derive(Resource)]
+struct MaxEffects(usize);
+
+impl MaxEffects {
+    fn from_env() -> Self {
+        Self(
+            std::env::var("SNAKE_MAX_EFFECTS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(MAX_EFFECTS),
+        )
+    }
+}
+
+/// Effect spawners take from `room` before spawning; at the cap, the effect
+/// is skipped. Spawns are deferred commands the query can't see yet, so a
+/// spawner counts its own copy down instead of asking again.
+#[derive(SystemParam)]
+struct EffectBudget<'w, 's> {
+    max: Res<'w, MaxEffects>,
+    /// The path review is one burst of many markers, not a per-event
+    /// effect, so it doesn't take up the budget.
+    live: Query<'w, 's, (), (With<Lifetime>, Without<PathMarker>)>,
+}
+
+impl EffectBudget<'_, '_> {
+    fn room(&self) -> usize {
+        self.max.0.saturating_sub(self.live.iter().count())
+    }
+}
+
+/// Tags every transient entity that belongs to a run, so teardown is one query.
+#[derive(Component)]
+struct GameWorld;
+
+/// A pickup that starts a timed effect when the head reaches it.
+#[derive(Component, Clone, Copy, PartialEq)]
+enum PowerUp {
+    /// The head grows to a 2x2 block for `BIG_HEAD_SECONDS`: it eats
+    /// anything under it, and dies if any of it hits a wall or the body.
+    BigHead,
+    /// Walls, the body and other lethal cells are passed through; only the
+    /// arena edge holds the head back. Given on respawn by
+    /// `SpawnProtection`, never spawned as a pickup.
+    Shield,
+}
+
+impl PowerUp {
+    fn duration(self) -> f32 {
+        match self {
+            Self::BigHead => BIG_HEAD_SECONDS,
+            Self::Shield => SPAWN_PROTECTION_SECONDS,
+        }
+    }
+}
+
+/// Whether `PowerUp::BigHead` pickups appear every `POWER_UP_SPAWN_SECONDS`.
+/// Set with `power_ups=on` in `SETTINGS_PATH`.
+#[derive(Resource, Default)]
+struct PowerUps(bool);
+
+impl PowerUps {
+    fn from_settings(settings: &Settings) -> Result<Self, SnakeError> {
+        settings.flag("power_ups").map(Self)
+    }
+}
+
+/// Seconds of `PowerUp::Shield` after every respawn, so the player can get
+/// their bearings. Set with `spawn_protection` in `SETTINGS_PATH`, e.g. to
+/// `SPAWN_PROTECTION_SECONDS`; off (0) by default, since the shield holds the
+/// `ActiveEffect` slot and no power-up can be picked up while it lasts.
+#[derive(Resource, Default)]
+struct SpawnProtection(f32);
+
+impl SpawnProtection {
+    fn from_settings(settings: &Settings) -> Result<Self, SnakeError> {
+        settings
+            .get(
+                "spawn_protection",
+                "a number of seconds, 0 or more",
+                |value| value.parse::<f32>().ok().filter(|&secs| secs >= 0.0),
+            )
+            .map(|secs| Self(secs.unwrap_or(0.0)))
+    }
+}
+
+/// The power-up in effect, if any, and the time it has left.
+#[derive(Resource, Default)]
+struct ActiveEffect(Option<(PowerUp, Timer)>);
+
+impl ActiveEffect {
+    fn is(&self, power_up: PowerUp) -> bool {
+        self.0
+            .as_ref()
+            .is_some_and(|(active, _)| *active == power_up)
+    }
+
+    /// Cells the head covers at `head` heading `heading`. A big head spans
+    /// the 2x2 block ahead of and to the right of its cell, which keeps the
+    /// neck outside it whichever way the snake turns.
+    fn head_cells(&self, head: Position, heading: Direction) -> Vec<Position> {
+        if !self.is(PowerUp::BigHead) {
+            return vec![head];
+        }
+        let side = heading.rotated_right();
+        vec![
+            head,
+            head.step(heading),
+            head.step(side),
+            head.step(heading).step(side),
+        ]
+    }
+}
+
+/// A wall that only lasts the current run, like a Tron trail or the
+/// shrinking arena's rings. Unlike other walls it is cleared when the run
+/// ends.
+#[derive(Component)]
+struct RunWall;
+
+/// A lethal, permanent cell. Walls aren't `GameWorld`, so they outlive the run.
+#[derive(Component)]
+struct Wall;
+
+/// Drawn-only nudge from the cell center, in tiles.
+#[derive(Component, Default, PartialEq)]
+struct RenderOffset(Vec2);
+
+#[derive(Component)]
+struct Size {
+    width: f32,
+    height: f32,
+}
+
+impl Size {
+    pub fn square(x: f32) -> Self {
+        Self {
+            width: x,
+            height: x,
+        }
+    }
+}
+
+/// The whole game: the shared state, events and settings, plus
+/// `InputPlugin`, `RenderPlugin` and `GameplayPlugin`. Needs
+/// `DefaultPlugins`, or an equivalent set, already on the app.
+pub struct SnakePlugin;
+
+impl Plugin for SnakePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(DebugMode::from_env())
+            .init_resource::<FontHandle>()
+            .init_state::<GameState>()
+            .add_event::<GameEvent>()
+            .add_systems(PreStartup, load_config)
+            .add_plugins((InputPlugin, RenderPlugin, GameplayPlugin));
+    }
+}
+
+/// Arrow keys, dash and the input aids. A replacement has to fill
+/// `InputBuffer` with turns and set `DashPending`, which gameplay consumes.
+pub struct InputPlugin;
+
+impl Plugin for InputPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(DashPending::default())
+            .insert_resource(LastRegisteredDir::default())
+            .insert_resource(InputBuffer::default())
+            .insert_resource(InputVisualizer::default())
+            .add_systems(Startup, (spawn_input_visualizer, spawn_inverted_text))
+            .add_systems(
+                Update,
+                (
+                    snake_input_moviment
+                        .before(snake_movement)
+                        .run_if(in_state(GameState::Playing)),
+                    invert_controls.before(snake_input_moviment),
+                    input_visualizer.after(toggle_input_visualizer),
+                    toggle_input_visualizer,
+                ),
+            );
+    }
+}
+
+/// Camera, grid-to-window scaling and translation, themes, the HUD and every
+/// purely visual effect.
+pub struct RenderPlugin;
+
+impl Plugin for RenderPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(FrameCapture::from_env())
+            .add_systems(
+                Startup,
+                (
+                    setup_camera,
+                    spawn_background,
+                    load_snake_sprites,
+                    spawn_hud,
+                    load_window_icon,
+                    spawn_goal_progress,
+                    spawn_food_list,
+                    spawn_run_code,
+                    spawn_next_cell_ghost,
+                    spawn_stats_panel,
+                    spawn_compass,
+                    spawn_kiosk_countdown,
+                    spawn_safe_path_tiles.run_if(|hint: Res<SafePathHint>| hint.0),
+                ),
+            )
+            .add_systems(
+                Update,
+                (
+                    flicker_food.after(count_ticks),
+                    motion_blur.after(snake_input_moviment),
+                    capture_frame.after(snake_movement).run_if(movement_tick),
+                    update_hud.after(snake_eating).after(track_streak),
+                    animate_popups.after(despawn_expired),
+                    despawn_expired,
+                    apply_window_icon,
+                    fall_back_font,
+                    goal_progress.after(snake_growth),
+                    food_list.run_if(|debug: Res<DebugMode>| debug.0),
+                    toggle_theme,
+                    apply_theme.run_if(
+                        resource_changed::<Theme>
+                            .or_else(|added: Query<(), Added<Background>>| !added.is_empty()),
+                    ),
+                    next_cell_ghost.after(snake_input_moviment),
+                    animate_food_pull,
+                    stats_panel,
+                    compass.after(snake_movement),
+                    fit_background,
+                    fade_path_markers.after(despawn_expired),
+                ),
+            )
+            .add_systems(
+                Update,
+                (
+                    toggle_screen_shake,
+                    screen_shake.after(toggle_screen_shake),
+                    big_head.after(collect_power_ups).after(tick_active_effect),
+                    safe_path_hint
+                        .after(snake_movement)
+                        .run_if(movement_tick)
+                        .run_if(|hint: Res<SafePathHint>| hint.0),
+                ),
+            )
+            .add_systems(OnEnter(GameState::Won), show_win_screen)
+            .add_systems(OnExit(GameState::Won), despawn_screen::<WinScreen>)
+            .add_systems(OnEnter(GameState::Buried), show_buried_screen)
+            .add_systems(OnExit(GameState::Buried), despawn_screen::<BuriedScreen>)
+            .add_systems(
+                PostUpdate,
+                (
+                    wiggle_segments.before(position_translation),
+                    position_translation,
+                    size_scaling,
+                    apply_snake_sprites,
+                    snake_coloring.after(apply_snake_sprites),
+                    effect_glow.after(snake_coloring),
+                    board_coloring,
+                ),
+            )
+            .add_systems(
+                PostUpdate,
+                (
+                    explode_death.run_if(death_playing(DeathAnimation::Explode)),
+                    dissolve_death.run_if(death_playing(DeathAnimation::Dissolve)),
+                    blink_death.run_if(death_playing(DeathAnimation::Blink)),
+                    coast_death.run_if(death_playing(DeathAnimation::Coast)),
+                )
+                    .after(effect_glow)
+                    .after(wiggle_segments)
+                    .before(position_translation),
+            );
+    }
+}
+
+/// The rules: movement, eating, growth, spawning, dying and the run around
+/// them.
+pub struct GameplayPlugin;
+
+impl Plugin for GameplayPlugin {
+    fn build(&self, app: &mut App) {
+        let run = RunParams::default();
+        app.insert_resource(SnakeSegments::default())
+            .insert_resource(LastTailPosition::default())
+            .insert_resource(Score::default())
+            .insert_resource(Streak::default())
+            .insert_resource(PendingGrowth::default())
+            .insert_resource(TurnsSinceEat::default())
+            .insert_resource(DashedOver::default())
+            .insert_resource(MovementTimer::default())
+            .insert_resource(TickCount::default())
+            .insert_resource(LifeStats::default())
+            .insert_resource(ColorChallenge::default())
+            .insert_resource(SystemToggles::default())
+            .insert_resource(StepMode::default())
+            .insert_resource(DebugScenario::from_env())
+            .insert_resource(LengthGoal::from_env())
+            .insert_resource(MaxRunDuration::from_env())
+            .insert_resource(MaxEffects::from_env())
+            .insert_resource(GameRng::new(run.seed))
+            .insert_resource(run)
+            .insert_resource(CodeEntry::default())
+            .insert_resource(RunClock::default())
+            .insert_resource(RunPath::default())
+            .insert_resource(ActiveEffect::default())
+            .insert_resource(DeathSlowMo::default())
+            .add_systems(Startup, setup_snake)
+            .add_systems(
+                Update,
+                (
+                    snake_growth.after(snake_eating),
+                    snake_eating
+                        .after(snake_movement)
+                        .run_if(|toggles: Res<SystemToggles>| toggles.eating)
+                        .run_if(|style: Res<MovementStyle>| *style != MovementStyle::Tron),
+                    game_over.after(snake_movement),
+                    tick_movement_timer
+                        .before(snake_movement)
+                        .run_if(in_state(GameState::Playing)),
+                    snake_movement
+                        .run_if(movement_tick)
+                        .run_if(|toggles: Res<SystemToggles>| toggles.movement),
+                    count_ticks.before(snake_movement).run_if(movement_tick),
+                    color_challenge.before(snake_eating),
+                    food_spawner
+                        .run_if(in_state(GameState::Playing))
+                        .run_if(|toggles: Res<SystemToggles>| toggles.food_spawner)
+                        .run_if(|style: Res<MovementStyle>| *style != MovementStyle::Tron)
+                        .run_if(on_timer(Duration::from_secs(1))),
+                    tick_run_clock.run_if(in_state(GameState::Playing)),
+                    restart_after_win.run_if(in_state(GameState::Won)),
+                    death_lookahead.after(snake_movement).run_if(movement_tick),
+                    toggle_death_slow_mo,
+                    auto_balance.after(count_ticks),
+                    pin_speed.after(auto_balance),
+                    enter_run_code,
+                    update_run_code.after(enter_run_code),
+                    record_profile.before(game_over),
+                ),
+            )
+            .add_systems(
+                Update,
+                (
+                    toggle_systems.run_if(|debug: Res<DebugMode>| debug.0),
+                    debug_grow
+                        .before(snake_growth)
+                        .run_if(|debug: Res<DebugMode>| debug.0),
+                    check_segment_gaps
+                        .after(snake_movement)
+                        .run_if(movement_tick)
+                        .run_if(|debug: Res<DebugMode>| debug.0),
+                    kiosk_reset
+                        .before(game_over)
+                        .run_if(|limit: Res<MaxRunDuration>| limit.0.is_some()),
+                    step_simulation
+                        .before(tick_movement_timer)
+                        .before(count_ticks)
+                        .run_if(|debug: Res<DebugMode>| debug.0),
+                    record_path.after(snake_movement).run_if(movement_tick),
+                    play_scenario
+                        .before(snake_movement)
+                        .run_if(|debug: Res<DebugMode>| debug.0),
+                    power_up_spawner
+                        .run_if(in_state(GameState::Playing))
+                        .run_if(|power_ups: Res<PowerUps>| power_ups.0)
+                        .run_if(on_timer(Duration::from_secs(POWER_UP_SPAWN_SECONDS))),
+                    collect_power_ups
+                        .after(snake_movement)
+                        .run_if(movement_tick),
+                    tick_active_effect
+                        .before(snake_movement)
+                        .run_if(in_state(GameState::Playing)),
+                    split_food
+                        .after(snake_eating)
+                        .run_if(|splitting: Res<SplittingFood>| splitting.0),
+                    shrink_arena
+                        .after(snake_movement)
+                        .run_if(in_state(GameState::Playing))
+                        .run_if(|arena: Res<ShrinkingArena>| arena.timer.is_some()),
+                    flee_food
+                        .after(snake_eating)
+                        .after(count_ticks)
+                        .run_if(movement_tick)
+                        .run_if(|moving: Res<MovingFood>| moving.0),
+                    track_streak.after(snake_eating),
+                ),
+            );
+    }
+}
+
+fn setup_camera(mut command: Commands) {
+    command.spawn(Camera2dBundle::default());
+}
+
+fn spawn_background(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    commands.spawn((
+        MaterialMesh2dBundle {
+            mesh: meshes.add(Rectangle::new(1.0, 1.0)).into(),
+            material: materials.add(ColorMaterial::default()),
+            transform: Transform::from_xyz(0.0, 0.0, BACKGROUND_Z),
+            ..Default::default()
+        },
+        Background,
+    ));
+}
+
+/// Stretches the unit background quad over the whole window.
+fn fit_background(
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut backgrounds: Query<&mut Transform, With<Background>>,
+) {
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+    let scale = Vec3::new(window.width(), window.height(), 1.0);
+    for mut transform in backgrounds.iter_mut() {
+        if transform.scale != scale {
+            transform.scale = scale;
+        }
+    }
+}
+
+fn load_snake_sprites(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut layouts: ResMut<Assets<TextureAtlasLayout>>,
+) {
+    commands.insert_resource(SnakeSprites {
+        image: asset_server.load(SNAKE_SPRITES_PATH),
+        layout: layouts.add(TextureAtlasLayout::from_grid(
+            Vec2::splat(SNAKE_SPRITE_TILE),
+            4,
+            4,
+            None,
+            None,
+        )),
+    });
+}
+
+fn spawn_hud(mut commands: Commands, font: Res<FontHandle>) {
+    let style = font.style(20.0, Color::WHITE);
+    commands.spawn((
+        TextBundle::from_sections([
+            TextSection::new("", style.clone()),
+            TextSection::new("", style),
+        ])
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            left: Val::Px(10.0),
+            top: Val::Px(10.0),
+            ..Default::default()
+        }),
+        HudText,
+    ));
+}
+
+fn spawn_food_list(mut commands: Commands, font: Res<FontHandle>, debug: Res<DebugMode>) {
+    if !debug.0 {
+        return;
+    }
+    commands.spawn((
+        TextBundle::from_section("", font.style(14.0, Color::WHITE)).with_style(Style {
+            position_type: PositionType::Absolute,
+            left: Val::Px(10.0),
+            bottom: Val::Px(10.0),
+            ..Default::default()
+        }),
+        FoodList,
+    ));
+}
+
+fn spawn_goal_progress(mut commands: Commands) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(10.0),
+                    top: Val::Px(40.0),
+                    width: Val::Px(200.0),
+                    height: Val::Px(10.0),
+                    ..Default::default()
+                },
+                background_color: GOAL_BAR_BACKGROUND.into(),
+                visibility: Visibility::Hidden,
+                ..Default::default()
+            },
+            GoalProgressRoot,
+        ))
+        .with_children(|bar| {
+            bar.spawn((
+                NodeBundle {
+                    style: Style {
+                        width: Val::Percent(0.0),
+                        height: Val::Percent(100.0),
+                        ..Default::default()
+                    },
+                    background_color: GOAL_BAR_COLOR.into(),
+                    ..Default::default()
+                },
+                GoalProgressFill,
+            ));
+        });
+}
+
+fn spawn_input_visualizer(mut commands: Commands, font: Res<FontHandle>) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    right: Val::Px(10.0),
+                    bottom: Val::Px(10.0),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    row_gap: Val::Px(4.0),
+                    ..Default::default()
+                },
+                visibility: Visibility::Hidden,
+                ..Default::default()
+            },
+            InputVisualizerRoot,
+        ))
+        .with_children(|root| {
+            spawn_input_arrow(root, &font, KeyCode::ArrowUp, "^");
+            root.spawn(NodeBundle {
+                style: Style {
+                    column_gap: Val::Px(4.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .with_children(|row| {
+                spawn_input_arrow(row, &font, KeyCode::ArrowLeft, "<");
+                spawn_input_arrow(row, &font, KeyCode::ArrowDown, "v");
+                spawn_input_arrow(row, &font, KeyCode::ArrowRight, ">");
+            });
+        });
+}
+
+fn spawn_input_arrow(parent: &mut ChildBuilder, font: &FontHandle, key: KeyCode, label: &str) {
+    parent
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Px(24.0),
+                    height: Val::Px(24.0),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..Default::default()
+                },
+                background_color: INPUT_ARROW_IDLE_COLOR.into(),
+                ..Default::default()
+            },
+            InputArrow(key),
+        ))
+        .with_children(|arrow| {
+            arrow.spawn(TextBundle::from_section(
+                label,
+                font.style(18.0, Color::WHITE),
+            ));
+        });
+}
+
+fn load_window_icon(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(WindowIcon(Some(asset_server.load(WINDOW_ICON_PATH))));
+}
+
+/// Swaps a font that failed to load for the built-in one, both in the
+/// resource and in any text already spawned with it.
+fn fall_back_font(
+    asset_server: Res<AssetServer>,
+    mut font: ResMut<FontHandle>,
+    mut texts: Query<&mut Text>,
+) {
+    if asset_server.get_load_state(&font.0) != Some(LoadState::Failed) {
+        return;
+    }
+    warn!("cannot load {HUD_FONT_PATH}, using the default font");
+
+    let failed = std::mem::take(&mut font.0);
+    for mut text in texts.iter_mut() {
+        for section in text.sections.iter_mut() {
+            if section.style.font == failed {
+                section.style.font = Handle::default();
+            }
+        }
+    }
+}
+
+fn apply_window_icon(
+    mut icon: ResMut<WindowIcon>,
+    asset_server: Res<AssetServer>,
+    images: Res<Assets<Image>>,
+    winit_windows: NonSend<WinitWindows>,
+    windows: Query<Entity, With<PrimaryWindow>>,
+) {
+    let Some(handle) = icon.0.as_ref() else {
+        return;
+    };
+    match asset_server.get_load_state(handle) {
+        Some(LoadState::Loaded) => {}
+        Some(LoadState::Failed) | None => {
+            icon.0 = None;
+            return;
+        }
+        _ => return,
+    }
+
+    let Some(image) = images.get(handle) else {
+        return;
+    };
+    let Some(window) = windows
+        .get_single()
+        .ok()
+        .and_then(|window| winit_windows.get_window(window))
+    else {
+        return;
+    };
+
+    let size = image.texture_descriptor.size;
+    match Icon::from_rgba(image.data.clone(), size.width, size.height) {
+        Ok(rgba) => window.set_window_icon(Some(rgba)),
+        Err(err) => warn!("cannot use {WINDOW_ICON_PATH} as the window icon: {err}"),
+    }
+    icon.0 = None;
+}
+
+fn setup_snake(commands: Commands, segments: ResMut<SnakeSegments>, start: Res<Start>) {
+    spawn_snake(
+        commands,
+        segments,
+        start.cell.unwrap_or_else(arena_center),
+        start.direction,
+    );
+}
+
+/// Keeps a respawn off the border, so the snake has room to react and its
+/// trailing segment stays on the board whatever the heading.
+fn safe_respawn_cell(near: Position) -> Position {
+    Position {
+        x: near.x.clamp(1, ARENA_WIDTH as i32 - 2),
+        y: near.y.clamp(1, ARENA_HEIGHT as i32 - 2),
+    }
+}
+
+fn spawn_snake(
+    mut commands: Commands,
+    mut segments: ResMut<SnakeSegments>,
+    start: Position,
+    direction: Direction,
+) {
+    let mut head_pos = start;
+    if !in_arena(head_pos) || !in_arena(head_pos.step(direction.opposite())) {
+        warn!(
+            "start position ({}, {}) does not fit the snake, using the arena center",
+            head_pos.x, head_pos.y
+        );
+        head_pos = arena_center();
+    }
+
+    let head = spawn_head(&mut commands, head_pos, direction);
+    let segment = spawn_segment(&mut commands, head_pos.step(direction.opposite()));
+    *segments = SnakeSegments(vec![head, segment]);
+}
+
+/// Spawns a snake laid out on `cells`, head first, for reproducing a
+/// situation. The cells must be on the board, distinct, and each next to the
+/// one before it.
+fn spawn_snake_from(
+    commands: &mut Commands,
+    segments: &mut SnakeSegments,
+    cells: &[Position],
+    direction: Direction,
+) -> Result<(), String> {
+    let Some((&head_pos, body)) = cells.split_first() else {
+        return Err("a snake needs at least a head".to_string());
+    };
+    if let Some(off) = cells.iter().find(|&&cell| !in_arena(cell)) {
+        return Err(format!("({}, {}) is off the board", off.x, off.y));
+    }
+    if let Some(gap) = cells
+        .windows(2)
+        .find(|pair| pair[0].manhattan(pair[1]) != 1)
+    {
+        return Err(format!(
+            "({}, {}) and ({}, {}) are not neighbours",
+            gap[0].x, gap[0].y, gap[1].x, gap[1].y
+        ));
+    }
+    if cells.iter().collect::<HashSet<_>>().len() != cells.len() {
+        return Err("a cell is used twice".to_string());
+    }
+
+    let mut spawned = vec![spawn_head(commands, head_pos, direction)];
+    spawned.extend(body.iter().map(|&cell| spawn_segment(commands, cell)));
+    *segments = SnakeSegments(spawned);
+    Ok(())
+}
+
+fn spawn_head(commands: &mut Commands, head_pos: Position, direction: Direction) -> Entity {
+    commands
+        .spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: SNAKE_HEAD_COLOR,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            SnakeHead { direction },
+            SnakeSegment,
+            GameWorld,
+            head_pos,
+            RenderOffset::default(),
+            Size::square(HEAD_SIZE),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: MOTION_BLUR_COLOR,
+                        ..Default::default()
+                    },
+                    visibility: Visibility::Hidden,
+                    ..Default::default()
+                },
+                MotionBlurStreak,
+            ));
+        })
+        .id()
+}
+
+fn spawn_segment(command: &mut Commands, position: Position) -> Entity {
+    command
+        .spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: SNAKE_SEGMENT_COLOR,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            SnakeSegment,
+            GameWorld,
+            position,
+            RenderOffset::default(),
+            Size::square(0.65),
+        ))
+        .id()
+}
+
+fn tick_movement_timer(time: Res<Time>, mut timer: ResMut<MovementTimer>) {
+    timer.0.tick(time.delta());
+}
+
+/// The timer stops ticking outside `Playing`, which would otherwise leave
+/// `just_finished` latched for every frame until play resumes.
+fn movement_tick(
+    timer: Res<MovementTimer>,
+    state: Res<State<GameState>>,
+    slow_mo: Res<DeathSlowMo>,
+    step: Res<StepMode>,
+) -> bool {
+    let due = if step.paused {
+        step.step_requested
+    } else {
+        timer.0.just_finished()
+    };
+    due && *state.get() == GameState::Playing && slow_mo.freeze.is_none()
+}
+
+fn count_ticks(mut ticks: ResMut<TickCount>) {
+    ticks.0 += 1;
+}
+
+fn pin_speed(
+    constant: Res<ConstantSpeed>,
+    slow_mo: Res<DeathSlowMo>,
+    mut timer: ResMut<MovementTimer>,
+) {
+    // The death lead-in is a replay effect, not a speed change, and it puts
+    // the interval back itself.
+    if !constant.enabled || slow_mo.lead_in.is_some() {
+        return;
+    }
+    let interval = Duration::from_secs_f32(constant.interval);
+    if timer.0.duration() != interval {
+        timer.0.set_duration(interval);
+    }
+}
+
+fn auto_balance(
+    balance: Res<AutoBalance>,
+    constant: Res<ConstantSpeed>,
+    ticks: Res<TickCount>,
+    slow_mo: Res<DeathSlowMo>,
+    mut stats: ResMut<LifeStats>,
+    mut timer: ResMut<MovementTimer>,
+    mut events: EventReader<GameEvent>,
+) {
+    // Wall and body hits can both land on one tick; that is still one life.
+    let deaths = events
+        .read()
+        .filter(|event| **event == GameEvent::GameOver)
+        .count();
+    if deaths > 0 {
+        stats.end_life(ticks.0);
+    }
+
+    // The death lead-in owns the interval until it restores it.
+    if !balance.0 || constant.enabled || slow_mo.lead_in.is_some() {
+        return;
+    }
+    let t = (stats.mean(ticks.0) / AUTO_BALANCE_TARGET_TICKS).min(1.0);
+    let interval = Duration::from_secs_f32(
+        AUTO_BALANCE_SLOWEST + (AUTO_BALANCE_FASTEST - AUTO_BALANCE_SLOWEST) * t,
+    );
+    if timer.0.duration() != interval {
+        timer.0.set_duration(interval);
+    }
+}
+
+fn flicker_food(
+    ticks: Res<TickCount>,
+    mut food: Query<&mut Visibility, (With<Food>, With<FlickerFood>)>,
+) {
+    for mut visibility in food.iter_mut() {
+        *visibility = if ticks.0 % 2 == 0 {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+}
+
+fn snake_eating(
+    mut command: Commands,
+    mut growth_writter: EventWriter<GameEvent>,
+    mut score: ResMut<Score>,
+    scoring: Scoring,
+    segments: Res<SnakeSegments>,
+    challenge: Res<ColorChallenge>,
+    reach: EatReach,
+    mut dashed_over: ResMut<DashedOver>,
+    ticks: Res<TickCount>,
+    mut turns: ResMut<TurnsSinceEat>,
+    font: Res<FontHandle>,
+    effects: EffectBudget,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    food_position: Query<(
+        Entity,
+        &Food,
+        &FoodTier,
+        &Position,
+        &FoodColor,
+        Has<FlickerFood>,
+        Has<SplitterFood>,
+    )>,
+    head_position: Query<(&SnakeHead, &Position)>,
+) {
+    let dashed_over = dashed_over.0.take();
+    let mut room = effects.room();
+    // `track_streak` runs after this, so food eaten earlier this tick (a dash
+    // can eat twice) is counted here.
+    let mut streak = scoring.streak.0;
+    let mut take_room = || {
+        let free = room > 0;
+        room = room.saturating_sub(1);
+        free
+    };
+    for (head, head_pos) in head_position.iter() {
+        for (ent, food, tier, food_pos, color, flicker, splitter) in food_position.iter() {
+            if flicker && ticks.0 % 2 != 0 {
+                continue;
+            }
+            let in_reach = reach.reaches(*head_pos, head.direction, *food_pos);
+            if in_reach || Some(*food_pos) == dashed_over {
+                command.entity(ent).despawn();
+                if food_pos != head_pos && in_reach && take_room() {
+                    spawn_food_pull(&mut command, *food_pos, *head_pos, *color, *tier);
+                }
+                if !challenge.enabled || *color == challenge.target {
+                    for _ in 0..food.grow {
+                        growth_writter.send(GameEvent::Growth);
+                    }
+                    let award = scoring.mode.award(food.value, segments.0.len())
+                        + scoring.streak_bonus.0.saturating_mul(streak);
+                    streak += 1;
+                    score.0 += award;
+                    growth_writter.send(GameEvent::Ate);
+                    if splitter {
+                        growth_writter.send(GameEvent::Split(*food_pos));
+                    }
+                    if *scoring.mode == ScoringMode::Length {
+                        if let Some(window) = windows.get_single().ok().filter(|_| take_room()) {
+                            spawn_popup(
+                                &mut command,
+                                &font,
+                                &format!("+{award}"),
+                                *food_pos,
+                                window,
+                            );
+                        }
+                    }
+
+                    if turns.0 == Some(0) {
+                        score.0 += STRAIGHT_SHOT_BONUS;
+                        if let Some(window) = windows.get_single().ok().filter(|_| take_room()) {
+                            spawn_popup(&mut command, &font, "Straight shot!", *head_pos, window);
+                        }
+                    }
+                    turns.0 = Some(0);
+                } else {
+                    score.0 = score.0.saturating_sub(1);
+                }
+            }
+        }
+    }
+}
+
+fn spawn_food_pull(
+    command: &mut Commands,
+    from: Position,
+    to: Position,
+    color: FoodColor,
+    tier: FoodTier,
+) {
+    command.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: color.color(),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        FoodPull(Vec2::new((to.x - from.x) as f32, (to.y - from.y) as f32)),
+        Lifetime(Timer::from_seconds(FOOD_PULL_SECONDS, TimerMode::Once)),
+        GameWorld,
+        color,
+        tier,
+        from,
+        RenderOffset::default(),
+        Size::square(0.8),
+    ));
+}
+
+fn animate_food_pull(mut pulls: Query<(&FoodPull, &Lifetime, &mut RenderOffset)>) {
+    for (pull, lifetime, mut offset) in pulls.iter_mut() {
+        offset.0 = pull.0 * lifetime.0.fraction();
+    }
+}
+
+fn spawn_popup(
+    command: &mut Commands,
+    font: &FontHandle,
+    text: &str,
+    at: Position,
+    window: &Window,
+) {
+    command.spawn((
+        Text2dBundle {
+            text: Text::from_section(text, font.style(24.0, Color::WHITE)),
+            transform: Transform::from_translation(Grid::new(window).cell_center(at) + Vec3::Z),
+            ..Default::default()
+        },
+        Popup,
+        Lifetime(Timer::from_seconds(POPUP_SECONDS, TimerMode::Once)),
+        GameWorld,
+    ));
+}
+
+fn animate_popups(
+    time: Res<Time>,
+    theme: Res<Theme>,
+    mut popups: Query<(&Lifetime, &mut Transform, &mut Text), With<Popup>>,
+) {
+    if !theme.animated() {
+        return;
+    }
+    for (lifetime, mut transform, mut text) in popups.iter_mut() {
+        transform.translation.y += POPUP_RISE_SPEED * time.delta_seconds();
+        for section in text.sections.iter_mut() {
+            section.style.color.set_a(1.0 - lifetime.0.fraction());
+        }
+    }
+}
+
+fn despawn_expired(
+    mut command: Commands,
+    time: Res<Time>,
+    mut expiring: Query<(Entity, &mut Lifetime)>,
+) {
+    for (ent, mut lifetime) in expiring.iter_mut() {
+        if lifetime.0.tick(time.delta()).finished() {
+            command.entity(ent).despawn_recursive();
+        }
+    }
+}
+
+fn color_challenge(
+    time: Res<Time>,
+    input: Res<ButtonInput<KeyCode>>,
+    mut challenge: ResMut<ColorChallenge>,
+) {
+    if input.just_pressed(KeyCode::F3) {
+        challenge.enabled = !challenge.enabled;
+        challenge.timer.reset();
+    }
+    if challenge.enabled && challenge.timer.tick(time.delta()).just_finished() {
+        challenge.target = challenge.target.other();
+    }
+}
+
+fn snake_movement(
+    mut command: Commands,
+    mut segments: ResMut<SnakeSegments>,
+    mut heads: Query<(Entity, &mut SnakeHead)>,
+    mut buffer: ResMut<InputBuffer>,
+    mut last_tail_position: ResMut<LastTailPosition>,
+    mut game_over_writer: EventWriter<GameEvent>,
+    mut score: ResMut<Score>,
+    mut rules: CollisionRules,
+    debug: Res<DebugMode>,
+    mut dash: ResMut<DashPending>,
+    mut dashed_over: ResMut<DashedOver>,
+    mut turns: ResMut<TurnsSinceEat>,
+    mut last_heading: Local<Option<Direction>>,
+    mut positions: Query<&mut Position, Without<Wall>>,
+) {
+    dashed_over.0 = None;
+
+    // A segment despawned elsewhere but still listed would panic the lookups
+    // below, so drop it and carry on.
+    if segments.0.iter().any(|&e| !positions.contains(e)) {
+        let listed = segments.0.len();
+        segments.0.retain(|&e| positions.contains(e));
+        if debug.0 {
+            warn!(
+                "dropped {} stale snake segment(s)",
+                listed - segments.0.len()
+            );
+        }
+    }
+    if segments.0.is_empty() {
+        return;
+    }
+
+    if let Some((head_entity, mut head)) = heads.iter_mut().next() {
+        if let Some(turn) = buffer.0.pop_front() {
+            head.direction = turn;
+        }
+
+        if last_heading.is_some_and(|heading| heading != head.direction) {
+            if let Some(turns) = turns.0.as_mut() {
+                *turns += 1;
+            }
+        }
+        *last_heading = Some(head.direction);
+
+        // A dash is two full steps, so the skipped cell still gets the
+        // wall, body and food checks.
+        let steps = if std::mem::take(&mut dash.0) { 2 } else { 1 };
+        for step in 0..steps {
+            let ahead = positions.get(head_entity).unwrap().step(head.direction);
+            let footprint = rules.effect.head_cells(ahead, head.direction);
+            let shielded = rules.effect.is(PowerUp::Shield);
+            if shielded && !in_arena(ahead) {
+                break;
+            }
+            let blocked = !shielded
+                && footprint
+                    .iter()
+                    .any(|&cell| !in_arena(cell) || rules.walls.iter().any(|wall| *wall == cell));
+            if rules.grace.enabled && rules.toggles.collision && blocked && !rules.grace.holding {
+                rules.grace.holding = true;
+                break;
+            }
+            rules.grace.holding = false;
+
+            let mut dead = false;
+            let mut segment_positions: Vec<Position> = segments
+                .0
+                .iter()
+                .map(|&e| *positions.get_mut(e).unwrap())
+                .collect();
+            // A delayed segment already owns the cell behind the tail, which
+            // makes it the tail tip for this tick.
+            if !rules.pending_growth.0.is_empty() {
+                segment_positions.extend(last_tail_position.0);
+            }
+
+            let mut head_pos = positions.get_mut(head_entity).unwrap();
+            *head_pos = ahead;
+
+            if rules.toggles.collision && blocked {
+                game_over_writer.send(GameEvent::GameOver);
+                dead = true;
+            }
+
+            // Grazing the tail tip is a risky move worth a bonus; the tip moves
+            // out of the way this tick, so only the rest of the body is lethal.
+            if let Some(hit) = segment_positions
+                .iter()
+                .position(|pos| rules.toggles.collision && !shielded && *pos == *head_pos)
+            {
+                if hit == segment_positions.len() - 1 {
+                    score.0 += TAIL_GRAZE_BONUS;
+                } else if rules.severing.0 {
+                    // The bitten segment moves on this tick, so it survives;
+                    // everything behind it is cut off.
+                    for &cut in &segments.0[hit + 1..] {
+                        command.entity(cut).despawn_recursive();
+                    }
+                    segments.0.truncate(hit + 1);
+                    segment_positions.truncate(hit + 1);
+                } else {
+                    game_over_writer.send(GameEvent::GameOver);
+                    dead = true;
+                }
+            }
+
+            // The rest of a big head gets no graze bonus or severing: any body
+            // under it, bar the moving tail tip, is fatal.
+            let body = &segment_positions[..segment_positions.len() - 1];
+            if !dead
+                && rules.toggles.collision
+                && footprint[1..].iter().any(|cell| body.contains(cell))
+            {
+                game_over_writer.send(GameEvent::GameOver);
+                dead = true;
+            }
+
+            if step + 1 < steps {
+                dashed_over.0 = Some(*head_pos);
+            }
+
+            // Update the positions of the rest of the segments
+            for (pos, segment) in segment_positions.iter().zip(segments.0.iter().skip(1)) {
+                let mut segment_pos = positions.get_mut(*segment).unwrap();
+                *segment_pos = *pos;
+            }
+
+            *last_tail_position = LastTailPosition(Some(segment_positions[segments.0.len() - 1]));
+            if *rules.style == MovementStyle::Tron {
+                let vacated = segment_positions[segments.0.len() - 1];
+                if vacated != ahead && in_arena(vacated) {
+                    let wall = spawn_wall(&mut command, vacated);
+                    command.entity(wall).insert(RunWall);
+                }
+            }
+
+            if dead {
+                break;
+            }
+        }
+    }
+}
+
+/// Warns about any two neighbouring segments that aren't on adjacent cells,
+/// which means a movement tick left the body inconsistent.
+fn check_segment_gaps(segments: Res<SnakeSegments>, positions: Query<&Position>) {
+    // Segments grown this frame have no position until commands are applied.
+    let cells: Vec<Option<Position>> = segments
+        .0
+        .iter()
+        .map(|&e| positions.get(e).ok().copied())
+        .collect();
+    for (i, pair) in cells.windows(2).enumerate() {
+        let (Some(ahead), Some(behind)) = (pair[0], pair[1]) else {
+            continue;
+        };
+        let distance = (ahead.x - behind.x).abs() + (ahead.y - behind.y).abs();
+        if distance > 1 {
+            warn!(
+                "segments {} and {} are {} cells apart: ({}, {}) and ({}, {})",
+                i,
+                i + 1,
+                distance,
+                ahead.x,
+                ahead.y,
+                behind.x,
+                behind.y
+            );
+        }
+    }
+}
+
+fn snake_growth(
+    mut command: Commands,
+    last_tail_position: ResMut<LastTailPosition>,
+    mut segments: ResMut<SnakeSegments>,
+    mut growth_reader: EventReader<GameEvent>,
+    delay: Res<GrowDelay>,
+    mut pending: ResMut<PendingGrowth>,
+    ticks: Res<TickCount>,
+    goal: Res<LengthGoal>,
+    clock: Res<RunClock>,
+    mut next_state: ResMut<NextState<GameState>>,
+    positions: Query<&Position>,
+    walls: Query<(), With<Wall>>,
+) {
+    // A dash can eat twice in one tick, so grow once per event.
+    let eaten = growth_reader
+        .read()
+        .filter(|event| **event == GameEvent::Growth)
+        .count();
+
+    // Growth reserved on an earlier tick lands where the tail just left,
+    // exactly where an instant segment would have moved to by now.
+    let due = pending.0.iter().filter(|&&tick| tick < ticks.0).count();
+    pending.0.retain(|&tick| tick >= ticks.0);
+    let grow_now = if delay.0 {
+        pending.0.extend(std::iter::repeat(ticks.0).take(eaten));
+        due
+    } else {
+        due + eaten
+    };
+
+    // Before the first move there is no last tail position yet, so the new
+    // segment starts on the tail and trails it from the next tick on.
+    let Some(at) = last_tail_position.0.or_else(|| {
+        segments
+            .0
+            .last()
+            .and_then(|&tail| positions.get(tail).ok().copied())
+    }) else {
+        return;
+    };
+    for _ in 0..grow_now {
+        segments.0.push(spawn_segment(&mut command, at));
+    }
+    let grew = grow_now > 0;
+
+    if !grew {
+        return;
+    }
+    // A snake that fills every open cell leaves the spawner nowhere to put
+    // food, so that is a win whether or not a length goal is set.
+    let open_cells = (ARENA_WIDTH * ARENA_HEIGHT) as usize - walls.iter().count();
+    if segments.0.len() >= open_cells {
+        info!("board filled in {:.1}s", clock.0.elapsed_secs());
+        next_state.set(GameState::Won);
+    } else if goal.0.is_some_and(|goal| segments.0.len() >= goal) {
+        info!("length goal reached in {:.1}s", clock.0.elapsed_secs());
+        next_state.set(GameState::Won);
+    }
+}
+
+fn tick_run_clock(time: Res<Time>, mut clock: ResMut<RunClock>) {
+    clock.0.tick(time.delta());
+}
+
+fn goal_progress(
+    goal: Res<LengthGoal>,
+    segments: Res<SnakeSegments>,
+    mut roots: Query<&mut Visibility, With<GoalProgressRoot>>,
+    mut fills: Query<&mut Style, With<GoalProgressFill>>,
+) {
+    for mut visibility in roots.iter_mut() {
+        *visibility = if goal.0.is_some() {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+    if let Some(goal) = goal.0 {
+        let progress = (segments.0.len() as f32 / goal.max(1) as f32).min(1.0);
+        for mut style in fills.iter_mut() {
+            style.width = Val::Percent(progress * 100.0);
+        }
+    }
+}
+
+fn show_win_screen(
+    mut commands: Commands,
+    font: Res<FontHandle>,
+    clock: Res<RunClock>,
+    segments: Res<SnakeSegments>,
+) {
+    commands.spawn((
+        TextBundle::from_section(
+            format!(
+                "Length {} reached in {:.1}s!\nPress Enter to play again",
+                segments.0.len(),
+                clock.0.elapsed_secs()
+            ),
+            font.style(28.0, Color::WHITE),
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            left: Val::Percent(10.0),
+            top: Val::Percent(40.0),
+            ..Default::default()
+        }),
+        WinScreen,
+    ));
+}
+
+fn despawn_screen<T: Component>(mut commands: Commands, screens: Query<Entity, With<T>>) {
+    for screen in screens.iter() {
+        commands.entity(screen).despawn_recursive();
+    }
+}
+
+fn restart_after_win(
+    input: Res<ButtonInput<KeyCode>>,
+    mut events: EventWriter<GameEvent>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if input.just_pressed(KeyCode::Enter) {
+        events.send(GameEvent::GameOver);
+        next_state.set(GameState::Playing);
+    }
+}
+
+/// Samples the arrows once per frame and queues a turn only when the sampled
+/// direction changes, so the frame rate never changes how many turns a key
+/// produces: a held key queues one turn at 30 Hz or at 240 Hz alike.
+/// `KeyTrigger` decides whether a held key keeps counting or only the frame
+/// it went down; either way the reversal guard applies to every turn.
+fn snake_input_moviment(
+    input: Res<ButtonInput<KeyCode>>,
+    mut dash: ResMut<DashPending>,
+    mut last_registered: ResMut<LastRegisteredDir>,
+    mut buffer: ResMut<InputBuffer>,
+    slow_mo: Res<DeathSlowMo>,
+    scheme: Res<ControlScheme>,
+    trigger: Res<KeyTrigger>,
+    invert: Res<InvertControls>,
+    heads: Query<&SnakeHead>,
+) {
+    // Locked during the death lead-in so the slow motion can't be used to
+    // dodge a death that has already been decided.
+    if slow_mo.lead_in.is_some() {
+        return;
+    }
+
+    if input.just_pressed(KeyCode::Space) {
+        dash.0 = true;
+    }
+
+    if let Some(head) = heads.iter().next() {
+        if *scheme == ControlScheme::Relative {
+            // Every press is a new turn, so there is nothing to debounce.
+            let heading = buffer.0.back().copied().unwrap_or(head.direction);
+            let turned = if input.just_pressed(KeyCode::ArrowLeft) {
+                heading.rotated_left()
+            } else if input.just_pressed(KeyCode::ArrowRight) {
+                heading.rotated_right()
+            } else {
+                return;
+            };
+            // Inverted, a left turn becomes a right one.
+            let turned = if invert.0 { turned.opposite() } else { turned };
+            if buffer.0.len() < INPUT_BUFFER_SIZE {
+                buffer.0.push_back(turned);
+            }
+            return;
+        }
+
+        // A tap pressed and released within one slow frame is no longer
+        // `pressed` by the time it is sampled, but it still counts.
+        let down = |key| match *trigger {
+            KeyTrigger::Held => input.pressed(key) || input.just_pressed(key),
+            KeyTrigger::Tap => input.just_pressed(key),
+        };
+        let dir = if down(KeyCode::ArrowLeft) {
+            Direction::Left
+        } else if down(KeyCode::ArrowRight) {
+            Direction::Right
+        } else if down(KeyCode::ArrowDown) {
+            Direction::Down
+        } else if down(KeyCode::ArrowUp) {
+            Direction::Up
+        } else {
+            last_registered.0 = None;
+            return;
+        };
+
+        // Inverted before the reversal guard, so the flipped key still can't
+        // turn the snake back onto itself.
+        let dir = if invert.0 { dir.opposite() } else { dir };
+        if last_registered.0 == Some(dir) {
+            return;
+        }
+
+        let heading = buffer.0.back().copied().unwrap_or(head.direction);
+        if dir != heading && dir != heading.opposite() && buffer.0.len() < INPUT_BUFFER_SIZE {
+            buffer.0.push_back(dir);
+            last_registered.0 = Some(dir);
+        }
+    }
+}
+
+fn game_over(
+    mut command: Commands,
+    time: Res<Time>,
+    mut reader: EventReader<GameEvent>,
+    mut segments: ResMut<SnakeSegments>,
+    mut run: RunResources,
+    spawn: SpawnSettings,
+    mut slow_mo: ResMut<DeathSlowMo>,
+    mut timer: ResMut<MovementTimer>,
+    animation: Res<DeathAnimation>,
+    mut code: RunCode,
+    mut next_state: ResMut<NextState<GameState>>,
+    body: Query<&Position, With<SnakeSegment>>,
+    walls: Query<(Entity, &Position, Has<RunWall>), With<Wall>>,
+    markers: Query<Entity, With<PathMarker>>,
+    world: Query<Entity, With<GameWorld>>,
+) {
+    // Count rather than stop at the first match, so a wall hit and a body hit
+    // on the same tick don't leave a second game over queued for next frame.
+    let game_overs = reader
+        .read()
+        .filter(|event| **event == GameEvent::GameOver)
+        .count();
+
+    let hold = slow_mo.enabled || *animation != DeathAnimation::None;
+    if game_overs > 0 && hold && slow_mo.freeze.is_none() {
+        slow_mo.freeze = Some(Timer::from_seconds(DEATH_FREEZE_SECONDS, TimerMode::Once));
+        return;
+    }
+    if let Some(freeze) = slow_mo.freeze.as_mut() {
+        if !freeze.tick(time.delta()).finished() {
+            return;
+        }
+        slow_mo.freeze = None;
+    } else if game_overs == 0 {
+        return;
+    }
+
+    if let Some(interval) = slow_mo.lead_in.take() {
+        timer.0.set_duration(interval);
+    }
+
+    info!("game over, score: {}", run.score.0);
+    for marker in markers.iter() {
+        command.entity(marker).despawn();
+    }
+    let mut reviewed = HashSet::new();
+    for &pos in run.path.0.iter().filter(|&&pos| in_arena(pos)) {
+        if reviewed.insert(pos) {
+            spawn_path_marker(&mut command, pos);
+        }
+    }
+    run.reset();
+
+    let mut died_at = segments.0.first().and_then(|&e| body.get(e).ok().copied());
+    // Trails and rings only last their own run.
+    for (wall, _, _) in walls.iter().filter(|(_, _, trail)| *trail) {
+        command.entity(wall).despawn();
+    }
+    let mut walled: Vec<Position> = walls
+        .iter()
+        .filter(|(_, _, trail)| !trail)
+        .map(|(_, pos, _)| *pos)
+        .collect();
+    if code.start_run() {
+        // A replayed board starts from scratch, walls and all.
+        for (wall, _, _) in walls.iter().filter(|(_, _, trail)| !trail) {
+            command.entity(wall).despawn();
+        }
+        walled.clear();
+        died_at = None;
+    } else if code.corpse_walls.0 {
+        // The head may have died off the board or on top of its own body.
+        for &pos in segments.0.iter().filter_map(|&e| body.get(e).ok()) {
+            if in_arena(pos) && !walled.contains(&pos) {
+                spawn_wall(&mut command, pos);
+                walled.push(pos);
+            }
+        }
+    }
+    despawn_game_world(&mut command, &world);
+
+    let preferred = spawn.respawn_cell(died_at);
+    match open_start(preferred, spawn.start.direction, &walled) {
+        Some(respawn_at) => {
+            spawn_snake(command, segments, respawn_at, spawn.start.direction);
+            if spawn.protection.0 > 0.0 {
+                let timer = Timer::from_seconds(spawn.protection.0, TimerMode::Once);
+                run.effect.0 = Some((PowerUp::Shield, timer));
+            }
+        }
+        None => {
+            info!("no room left to start, the session is over");
+            segments.0.clear();
+            next_state.set(GameState::Buried);
+        }
+    }
+}
+
+/// The open cell closest to `preferred` that fits a new snake heading in
+/// `direction`, if the walls have left any.
+fn open_start(preferred: Position, direction: Direction, walls: &[Position]) -> Option<Position> {
+    let open = |pos: Position| in_arena(pos) && !walls.contains(&pos);
+    arena_cells()
+        .filter(|&pos| open(pos) && open(pos.step(direction.opposite())))
+        .min_by_key(|pos| (pos.x - preferred.x).abs() + (pos.y - preferred.y).abs())
+}
+
+fn spawn_path_marker(command: &mut Commands, position: Position) {
+    command.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: PATH_MARKER_COLOR,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        PathMarker,
+        Lifetime(Timer::from_seconds(PATH_REVIEW_SECONDS, TimerMode::Once)),
+        position,
+        Size::square(0.25),
+    ));
+}
+
+fn record_path(mut path: ResMut<RunPath>, heads: Query<&Position, With<SnakeHead>>) {
+    for &head in heads.iter() {
+        if path.0.last() != Some(&head) {
+            path.0.push(head);
+        }
+    }
+}
+
+fn fade_path_markers(mut markers: Query<(&Lifetime, &mut Sprite), With<PathMarker>>) {
+    for (lifetime, mut sprite) in markers.iter_mut() {
+        let alpha = PATH_MARKER_COLOR.a() * (1.0 - lifetime.0.fraction());
+        sprite.color.set_a(alpha);
+    }
+}
+
+fn spawn_wall(command: &mut Commands, position: Position) -> Entity {
+    command
+        .spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: WALL_COLOR,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            Wall,
+            position,
+            Size::square(0.9),
+        ))
+        .id()
+}
+
+fn show_buried_screen(mut commands: Commands, font: Res<FontHandle>) {
+    commands.spawn((
+        TextBundle::from_section(
+            "No room left for another snake.\nGame over for good.",
+            font.style(28.0, Color::WHITE),
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            left: Val::Percent(10.0),
+            top: Val::Percent(40.0),
+            ..Default::default()
+        }),
+        BuriedScreen,
+    ));
+}
+
+fn spawn_kiosk_countdown(mut commands: Commands, font: Res<FontHandle>) {
+    commands.spawn((
+        TextBundle::from_section("", font.style(16.0, Color::rgba(1.0, 1.0, 1.0, 0.6))).with_style(
+            Style {
+                position_type: PositionType::Absolute,
+                left: Val::Percent(45.0),
+                bottom: Val::Px(10.0),
+                ..Default::default()
+            },
+        ),
+        KioskCountdown,
+    ));
+}
+
+/// Resets a run that has outlived `MaxRunDuration`, whatever state it is in:
+/// walls are cleared and a game over starts a fresh snake. The run's age is
+/// wall-clock time, so it keeps counting on the win and buried screens.
+fn kiosk_reset(
+    mut commands: Commands,
+    time: Res<Time>,
+    limit: Res<MaxRunDuration>,
+    mut age: Local<Stopwatch>,
+    mut events: ParamSet<(EventReader<GameEvent>, EventWriter<GameEvent>)>,
+    mut next_state: ResMut<NextState<GameState>>,
+    walls: Query<Entity, With<Wall>>,
+    mut countdown: Query<&mut Text, With<KioskCountdown>>,
+) {
+    let restarted = events
+        .p0()
+        .read()
+        .filter(|event| **event == GameEvent::GameOver)
+        .count();
+    if restarted > 0 {
+        age.reset();
+    }
+    age.tick(time.delta());
+
+    let Some(limit) = limit.0 else {
+        return;
+    };
+    let left = limit.as_secs_f32() - age.elapsed_secs();
+    for mut text in countdown.iter_mut() {
+        text.sections[0].value = if left <= KIOSK_COUNTDOWN_SECONDS {
+            format!("Resetting in {:.0}", left.max(0.0).ceil())
+        } else {
+            String::new()
+        };
+    }
+
+    if left <= 0.0 {
+        info!(
+            "run reached its {:.0}s limit, resetting",
+            limit.as_secs_f32()
+        );
+        for wall in walls.iter() {
+            commands.entity(wall).despawn();
+        }
+        events.p1().send(GameEvent::GameOver);
+        next_state.set(GameState::Playing);
+        age.reset();
+    }
+}
+
+/// Starts the slow-motion lead-in when the move the next tick will make is
+/// fatal. Any lead-in from the previous tick is over by now, so the normal
+/// interval is restored first.
+fn death_lookahead(
+    mut slow_mo: ResMut<DeathSlowMo>,
+    mut timer: ResMut<MovementTimer>,
+    severing: Res<TailSevering>,
+    buffer: Res<InputBuffer>,
+    segments: Res<SnakeSegments>,
+    toggles: Res<SystemToggles>,
+    grace: Res<WallGrace>,
+    effect: Res<ActiveEffect>,
+    heads: Query<&SnakeHead>,
+    positions: Query<&Position>,
+    walls: Query<&Position, With<Wall>>,
+) {
+    if let Some(interval) = slow_mo.lead_in.take() {
+        timer.0.set_duration(interval);
+    }
+    if !slow_mo.enabled || !toggles.collision || effect.is(PowerUp::Shield) {
+        return;
+    }
+    let Some(head) = heads.iter().next() else {
+        return;
+    };
+
+    let body: Vec<Position> = segments
+        .0
+        .iter()
+        .filter_map(|&e| positions.get(e).ok().copied())
+        .collect();
+    let Some(&head_pos) = body.first() else {
+        return;
+    };
+
+    // The tail tip moves out of the way, so only the rest of the body bites.
+    let next = head_pos.step(buffer.0.front().copied().unwrap_or(head.direction));
+    let bites = body.len() > 2 && body[1..body.len() - 1].contains(&next);
+    // Wall grace gives its own warning tick, and locking input for the lead-in
+    // would take away the chance to turn.
+    let blocked = !in_arena(next) || walls.iter().any(|wall| *wall == next);
+    if (blocked && !grace.enabled) || (bites && !severing.0) {
+        slow_mo.lead_in = Some(timer.0.duration());
+        timer
+            .0
+            .set_duration(Duration::from_secs_f32(DEATH_SLOW_MO_INTERVAL));
+    }
+}
+
+fn toggle_death_slow_mo(input: Res<ButtonInput<KeyCode>>, mut slow_mo: ResMut<DeathSlowMo>) {
+    if input.just_pressed(KeyCode::F4) {
+        slow_mo.enabled = !slow_mo.enabled;
+    }
+}
+
+fn toggle_systems(input: Res<ButtonInput<KeyCode>>, mut toggles: ResMut<SystemToggles>) {
+    let toggles = &mut *toggles;
+    let mut flipped = false;
+    for (key, enabled) in [
+        (KeyCode::Digit1, &mut toggles.food_spawner),
+        (KeyCode::Digit2, &mut toggles.movement),
+        (KeyCode::Digit3, &mut toggles.eating),
+        (KeyCode::Digit4, &mut toggles.collision),
+    ] {
+        if input.just_pressed(key) {
+            *enabled = !*enabled;
+            flipped = true;
+        }
+    }
+
+    if flipped {
+        let disabled = toggles.disabled();
+        if disabled.is_empty() {
+            info!("all systems enabled");
+        } else {
+            info!("disabled systems: {}", disabled.join(", "));
+        }
+    }
+}
+
+fn debug_grow(input: Res<ButtonInput<KeyCode>>, mut events: EventWriter<GameEvent>) {
+    if input.just_pressed(KeyCode::KeyG) {
+        events.send(GameEvent::Growth);
+    }
+}
+
+fn play_scenario(
+    mut command: Commands,
+    input: Res<ButtonInput<KeyCode>>,
+    scenario: Res<DebugScenario>,
+    mut segments: ResMut<SnakeSegments>,
+    mut run: RunResources,
+    world: Query<Entity, With<GameWorld>>,
+) {
+    if !input.just_pressed(KeyCode::KeyR) {
+        return;
+    }
+    let Some(scenario) = &scenario.0 else {
+        warn!("no SNAKE_SCENARIO to play");
+        return;
+    };
+    // Nothing is spawned on an invalid layout, so the current run carries on.
+    if let Err(err) = spawn_snake_from(
+        &mut command,
+        &mut segments,
+        &scenario.cells,
+        scenario.direction,
+    ) {
+        warn!("cannot play SNAKE_SCENARIO: {err}");
+        return;
+    }
+    // The query only holds entities from before this frame, so the new snake
+    // is safe.
+    despawn_game_world(&mut command, &world);
+    run.reset();
+    if let Some(food) = scenario.food.filter(|&food| in_arena(food)) {
+        spawn_food(&mut command, food, FoodTier::Common, FoodColor::Magenta);
+    }
+}
+
+fn step_simulation(input: Res<ButtonInput<KeyCode>>, mut step: ResMut<StepMode>) {
+    if input.just_pressed(KeyCode::KeyP) {
+        step.paused = !step.paused;
+        info!(
+            "movement {}",
+            if step.paused { "paused" } else { "resumed" }
+        );
+    }
+    step.step_requested = step.paused && input.just_pressed(KeyCode::Period);
+}
+
+fn despawn_game_world(command: &mut Commands, world: &Query<Entity, With<GameWorld>>) {
+    for ent in world.iter() {
+        command.entity(ent).despawn_recursive();
+    }
+}
+
+fn spawn_next_cell_ghost(mut commands: Commands) {
+    let half = 0.5 - NEXT_CELL_EDGE / 2.0;
+    commands
+        .spawn((
+            SpatialBundle {
+                visibility: Visibility::Hidden,
+                ..Default::default()
+            },
+            NextCellGhost,
+            Position { x: 0, y: 0 },
+            Size::square(1.0),
+        ))
+        .with_children(|ghost| {
+            for (x, y, width, height) in [
+                (0.0, half, 1.0, NEXT_CELL_EDGE),
+                (0.0, -half, 1.0, NEXT_CELL_EDGE),
+                (-half, 0.0, NEXT_CELL_EDGE, 1.0),
+                (half, 0.0, NEXT_CELL_EDGE, 1.0),
+            ] {
+                ghost.spawn(SpriteBundle {
+                    sprite: Sprite {
+                        color: NEXT_CELL_COLOR,
+                        ..Default::default()
+                    },
+                    transform: Transform::from_xyz(x, y, 0.5)
+                        .with_scale(Vec3::new(width, height, 1.0)),
+                    ..Default::default()
+                });
+            }
+        });
+}
+
+/// Follows the queued turn, if any, so the outline moves as soon as a key is
+/// pressed. Hidden off the board, between runs and during the death freeze.
+fn next_cell_ghost(
+    show: Res<ShowNextCell>,
+    state: Res<State<GameState>>,
+    slow_mo: Res<DeathSlowMo>,
+    buffer: Res<InputBuffer>,
+    heads: Query<(&SnakeHead, &Position), Without<NextCellGhost>>,
+    mut ghosts: Query<(&mut Position, &mut Visibility), With<NextCellGhost>>,
+) {
+    let playing = *state.get() == GameState::Playing && slow_mo.freeze.is_none();
+    let next = heads
+        .iter()
+        .next()
+        .map(|(head, pos)| pos.step(buffer.0.front().copied().unwrap_or(head.direction)))
+        .filter(|&next| show.0 && playing && in_arena(next));
+
+    for (mut pos, mut visibility) in ghosts.iter_mut() {
+        match next {
+            Some(next) => {
+                pos.set_if_neq(next);
+                visibility.set_if_neq(Visibility::Inherited);
+            }
+            None => {
+                visibility.set_if_neq(Visibility::Hidden);
+            }
+        }
+    }
+}
+
+fn spawn_safe_path_tiles(mut commands: Commands) {
+    for position in arena_cells() {
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: SAFE_PATH_COLOR,
+                    ..Default::default()
+                },
+                visibility: Visibility::Hidden,
+                ..Default::default()
+            },
+            SafePathTile,
+            position,
+            Size::square(1.0),
+        ));
+    }
+}
+
+/// Open cells connected to `from`, including it, never crossing `blocked` or
+/// leaving the arena. Stops after `FLOOD_FILL_LIMIT` cells.
+fn reachable_area(from: Position, blocked: &HashSet<Position>) -> HashSet<Position> {
+    let mut area = HashSet::new();
+    if !in_arena(from) || blocked.contains(&from) {
+        return area;
+    }
+    let mut frontier = vec![from];
+    area.insert(from);
+    while let Some(pos) = frontier.pop() {
+        for direction in Direction::ALL {
+            let next = pos.step(direction);
+            if area.len() >= FLOOD_FILL_LIMIT {
+                return area;
+            }
+            if in_arena(next) && !blocked.contains(&next) && area.insert(next) {
+                frontier.push(next);
+            }
+        }
+    }
+    area
+}
+
+fn safe_path_hint(
+    segments: Res<SnakeSegments>,
+    positions: Query<&Position, Without<SafePathTile>>,
+    walls: Query<&Position, (With<Wall>, Without<SafePathTile>)>,
+    mut tiles: Query<(&Position, &mut Visibility), With<SafePathTile>>,
+) {
+    let blocked: HashSet<Position> = segments
+        .0
+        .iter()
+        .filter_map(|&segment| positions.get(segment).ok().copied())
+        .chain(walls.iter().copied())
+        .collect();
+
+    let mut safe = HashSet::new();
+    if let Some(head) = segments
+        .0
+        .first()
+        .and_then(|&head| positions.get(head).ok())
+    {
+        for direction in Direction::ALL {
+            let next = head.step(direction);
+            if safe.contains(&next) {
+                continue;
+            }
+            let area = reachable_area(next, &blocked);
+            if area.len() > safe.len() {
+                safe = area;
+            }
+        }
+    }
+
+    for (pos, mut visibility) in tiles.iter_mut() {
+        visibility.set_if_neq(if safe.contains(pos) {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        });
+    }
+}
+
+fn death_playing(
+    style: DeathAnimation,
+) -> impl FnMut(Res<DeathAnimation>, Res<DeathSlowMo>) -> bool + Clone {
+    move |animation, slow_mo| *animation == style && slow_mo.freeze.is_some()
+}
+
+fn death_progress(slow_mo: &DeathSlowMo) -> f32 {
+    slow_mo.freeze.as_ref().map_or(0.0, Timer::fraction)
+}
+
+fn explode_death(
+    slow_mo: Res<DeathSlowMo>,
+    segments: Res<SnakeSegments>,
+    mut parts: Query<(&mut Sprite, &mut RenderOffset)>,
+) {
+    let t = death_progress(&slow_mo);
+    for (i, &segment) in segments.0.iter().enumerate() {
+        if let Ok((mut sprite, mut offset)) = parts.get_mut(segment) {
+            // Golden-angle steps spread the segments evenly without any RNG.
+            let heading = Vec2::from_angle(i as f32 * 2.4);
+            offset.0 = heading * EXPLODE_DISTANCE * t;
+            sprite.color.set_a(1.0 - t);
+        }
+    }
+}
+
+fn dissolve_death(
+    slow_mo: Res<DeathSlowMo>,
+    segments: Res<SnakeSegments>,
+    mut sprites: Query<&mut Sprite>,
+) {
+    let t = death_progress(&slow_mo);
+    let count = segments.0.len() as f32;
+    for (i, &segment) in segments.0.iter().enumerate() {
+        if let Ok(mut sprite) = sprites.get_mut(segment) {
+            let from_tail = count - 1.0 - i as f32;
+            sprite
+                .color
+                .set_a(1.0 - (t * count - from_tail).clamp(0.0, 1.0));
+        }
+    }
+}
+
+/// Each segment slides along the body toward where the segments ahead of it
+/// were, and the head carries on past its last cell along its heading.
+fn coast_death(
+    slow_mo: Res<DeathSlowMo>,
+    segments: Res<SnakeSegments>,
+    heads: Query<&SnakeHead>,
+    positions: Query<&Position>,
+    mut offsets: Query<&mut RenderOffset>,
+) {
+    let Some(heading) = heads.iter().next().map(|head| head.direction.unit()) else {
+        return;
+    };
+    let cells: Vec<Vec2> = segments
+        .0
+        .iter()
+        .filter_map(|&segment| positions.get(segment).ok())
+        .map(|pos| Vec2::new(pos.x as f32, pos.y as f32))
+        .collect();
+    // Index `i` along the body, extended past the head when negative.
+    let along = |i: f32| -> Vec2 {
+        if i <= 0.0 {
+            return cells[0] - heading * i;
+        }
+        let before = i.floor() as usize;
+        let after = (before + 1).min(cells.len() - 1);
+        cells[before].lerp(cells[after], i.fract())
+    };
+
+    let slid = COAST_TICKS * (death_progress(&slow_mo) * 2.0).min(1.0);
+    for (i, &segment) in segments.0.iter().enumerate().take(cells.len()) {
+        if let Ok(mut offset) = offsets.get_mut(segment) {
+            offset.set_if_neq(RenderOffset(along(i as f32 - slid) - cells[i]));
+        }
+    }
+}
+
+fn blink_death(
+    slow_mo: Res<DeathSlowMo>,
+    segments: Res<SnakeSegments>,
+    mut sprites: Query<&mut Sprite>,
+) {
+    let shown = (death_progress(&slow_mo) * BLINK_COUNT * 2.0) as u32 % 2 == 1;
+    for &segment in segments.0.iter() {
+        if let Ok(mut sprite) = sprites.get_mut(segment) {
+            sprite.color.set_a(if shown { 1.0 } else { 0.0 });
+        }
+    }
+}
+
+fn motion_blur(
+    blur: Res<MotionBlur>,
+    theme: Res<Theme>,
+    timer: Res<MovementTimer>,
+    heads: Query<&SnakeHead>,
+    mut streaks: Query<(&mut Transform, &mut Visibility), With<MotionBlurStreak>>,
+) {
+    let speed = 1.0 / timer.0.duration().as_secs_f32();
+    let Some(head) = heads.iter().next() else {
+        return;
+    };
+
+    for (mut transform, mut visibility) in streaks.iter_mut() {
+        if !blur.0 || !theme.animated() || speed < MOTION_BLUR_MIN_SPEED {
+            *visibility = Visibility::Hidden;
+            continue;
+        }
+
+        // The streak lives in the head's local space, where 1.0 is one head.
+        let length = ((speed - MOTION_BLUR_MIN_SPEED) / MOTION_BLUR_MIN_SPEED + 0.25)
+            .min(MOTION_BLUR_MAX_LENGTH);
+        let behind = Position { x: 0, y: 0 }.step(head.direction.opposite());
+        let offset = 0.5 + length / 2.0;
+        transform.translation = Vec3::new(behind.x as f32 * offset, behind.y as f32 * offset, -0.1);
+        transform.scale = if behind.x != 0 {
+            Vec3::new(length, 1.0, 1.0)
+        } else {
+            Vec3::new(1.0, length, 1.0)
+        };
+        *visibility = Visibility::Inherited;
+    }
+}
+
+fn spawn_run_code(mut commands: Commands, font: Res<FontHandle>) {
+    commands.spawn((
+        TextBundle::from_section("", font.style(16.0, Color::WHITE)).with_style(Style {
+            position_type: PositionType::Absolute,
+            right: Val::Px(10.0),
+            top: Val::Px(10.0),
+            ..Default::default()
+        }),
+        RunCodeText,
+    ));
+}
+
+/// F7 starts typing a run code, Enter plays it from a fresh board and Escape
+/// cancels. An empty code stops replaying the last one.
+fn enter_run_code(
+    input: Res<ButtonInput<KeyCode>>,
+    mut typed: EventReader<ReceivedCharacter>,
+    mut entry: ResMut<CodeEntry>,
+    mut events: EventWriter<GameEvent>,
+) {
+    let Some(text) = entry.typing.as_mut() else {
+        typed.clear();
+        if input.just_pressed(KeyCode::F7) {
+            entry.typing = Some(String::new());
+        }
+        return;
+    };
+    for event in typed.read() {
+        text.extend(event.char.chars().filter(char::is_ascii_alphanumeric));
+    }
+
+    if input.just_pressed(KeyCode::Escape) {
+        entry.typing = None;
+    } else if input.just_pressed(KeyCode::Enter) {
+        let text = entry.typing.take().unwrap_or_default();
+        if text.is_empty() {
+            entry.pinned = false;
+        } else if let Some(params) = decode_run(&text) {
+            entry.pending = Some(params);
+            entry.pinned = true;
+            events.send(GameEvent::GameOver);
+        } else {
+            warn!("{text} is not a run code for this arena");
+        }
+    }
+}
+
+fn update_run_code(
+    params: Res<RunParams>,
+    entry: Res<CodeEntry>,
+    mut text: Query<&mut Text, With<RunCodeText>>,
+) {
+    for mut text in text.iter_mut() {
+        text.sections[0].value = match &entry.typing {
+            Some(typing) => format!("Code: {typing}_"),
+            None => format!("Run {}", encode_run(&params)),
+        };
+    }
+}
+
+fn spawn_compass(mut commands: Commands, font: Res<FontHandle>) {
+    commands.spawn((
+        TextBundle::from_section("", font.style(24.0, Color::WHITE)).with_style(Style {
+            position_type: PositionType::Absolute,
+            right: Val::Px(10.0),
+            top: Val::Px(34.0),
+            ..Default::default()
+        }),
+        CompassText,
+    ));
+}
+
+fn spawn_inverted_text(mut commands: Commands, font: Res<FontHandle>) {
+    commands.spawn((
+        TextBundle {
+            visibility: Visibility::Hidden,
+            ..TextBundle::from_section("CONTROLS INVERTED", font.style(18.0, Color::ORANGE))
+                .with_style(Style {
+                    position_type: PositionType::Absolute,
+                    right: Val::Px(10.0),
+                    top: Val::Px(64.0),
+                    ..Default::default()
+                })
+        },
+        InvertedText,
+    ));
+}
+
+fn invert_controls(
+    input: Res<ButtonInput<KeyCode>>,
+    mut invert: ResMut<InvertControls>,
+    mut text: Query<&mut Visibility, With<InvertedText>>,
+) {
+    if input.just_pressed(KeyCode::F10) {
+        invert.0 = !invert.0;
+        write_setting("invert_controls", if invert.0 { "on" } else { "off" });
+    }
+    for mut visibility in text.iter_mut() {
+        visibility.set_if_neq(if invert.0 {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        });
+    }
+}
+
+/// Rewrites the arrow only when the direction, or the setting, changes.
+fn compass(
+    input: Res<ButtonInput<KeyCode>>,
+    mut setting: ResMut<Compass>,
+    mut shown: Local<Option<Direction>>,
+    heads: Query<&SnakeHead>,
+    mut text: Query<&mut Text, With<CompassText>>,
+) {
+    if input.just_pressed(KeyCode::F9) {
+        setting.0 = !setting.0;
+        write_setting("compass", if setting.0 { "on" } else { "off" });
+    }
+
+    let direction = heads
+        .iter()
+        .next()
+        .map(|head| head.direction)
+        .filter(|_| setting.0);
+    if direction == *shown && !setting.is_changed() {
+        return;
+    }
+    *shown = direction;
+    for mut text in text.iter_mut() {
+        text.sections[0].value = direction.map_or("", Direction::arrow).to_string();
+    }
+}
+
+fn spawn_stats_panel(mut commands: Commands, font: Res<FontHandle>) {
+    commands.spawn((
+        TextBundle {
+            visibility: Visibility::Hidden,
+            ..TextBundle::from_section("", font.style(20.0, Color::WHITE)).with_style(Style {
+                position_type: PositionType::Absolute,
+                left: Val::Percent(25.0),
+                top: Val::Percent(35.0),
+                ..Default::default()
+            })
+        },
+        StatsPanel,
+    ));
+}
+
+fn stats_panel(
+    input: Res<ButtonInput<KeyCode>>,
+    profile: Res<Profile>,
+    mut panels: Query<(&mut Text, &mut Visibility), With<StatsPanel>>,
+) {
+    for (mut text, mut visibility) in panels.iter_mut() {
+        if input.just_pressed(KeyCode::F8) {
+            *visibility = match *visibility {
+                Visibility::Hidden => Visibility::Inherited,
+                _ => Visibility::Hidden,
+            };
+        }
+        if *visibility != Visibility::Hidden && (profile.is_changed() || visibility.is_changed()) {
+            text.sections[0].value = format!(
+                "Games played: {}\nApples eaten: {}\nTime played: {:.0}s\nBest score: {}",
+                profile.games_played,
+                profile.apples_eaten,
+                profile.play_time_secs,
+                profile.best_score
+            );
+        }
+    }
+}
+
+/// Folds the run into the profile. Runs before `game_over` so the score and
+/// clock haven't been reset yet.
+fn record_profile(
+    score: Res<Score>,
+    clock: Res<RunClock>,
+    mut profile: ResMut<Profile>,
+    mut events: EventReader<GameEvent>,
+) {
+    let mut ended = false;
+    for event in events.read() {
+        match event {
+            GameEvent::Ate => profile.apples_eaten += 1,
+            GameEvent::GameOver => ended = true,
+            GameEvent::Growth | GameEvent::Split(_) => {}
+        }
+    }
+
+    // Wall and body hits can both land on one tick; that is still one game.
+    if ended {
+        profile.games_played += 1;
+        profile.play_time_secs += clock.0.elapsed_secs();
+        profile.best_score = profile.best_score.max(score.0);
+        profile.save();
+    }
+}
+
+/// Counts food eaten in a row; a death starts the count over.
+fn track_streak(mut streak: ResMut<Streak>, mut events: EventReader<GameEvent>) {
+    for event in events.read() {
+        match event {
+            GameEvent::Ate => streak.0 += 1,
+            GameEvent::GameOver => streak.0 = 0,
+            GameEvent::Growth | GameEvent::Split(_) => {}
+        }
+    }
+}
+
+fn update_hud(
+    score: Res<Score>,
+    streak: Res<Streak>,
+    challenge: Res<ColorChallenge>,
+    mut hud: Query<&mut Text, With<HudText>>,
+) {
+    for mut text in hud.iter_mut() {
+        text.sections[0].value = format!("Score: {}  Streak: {}", score.0, streak.0);
+        if challenge.enabled {
+            text.sections[1].value = format!("  Target: {}", challenge.target.name());
+            text.sections[1].style.color = challenge.target.color();
+        } else {
+            text.sections[1].value.clear();
+        }
+    }
+}
+
+fn food_list(
+    mut list: Query<&mut Text, With<FoodList>>,
+    food: Query<(
+        &Food,
+        &Position,
+        &FoodColor,
+        Has<FlickerFood>,
+        Option<&Lifetime>,
+    )>,
+) {
+    let mut lines = vec![format!("Food: {}", food.iter().len())];
+    for (food, pos, color, flicker, lifetime) in food.iter() {
+        let mut line = format!("({}, {}) {} +{}", pos.x, pos.y, color.name(), food.value);
+        if flicker {
+            line.push_str(" flicker");
+        }
+        if let Some(lifetime) = lifetime {
+            line.push_str(&format!(" {:.1}s", lifetime.0.remaining_secs()));
+        }
+        lines.push(line);
+    }
+
+    for mut text in list.iter_mut() {
+        text.sections[0].value = lines.join("\n");
+    }
+}
+
+fn toggle_input_visualizer(
+    input: Res<ButtonInput<KeyCode>>,
+    mut visualizer: ResMut<InputVisualizer>,
+) {
+    if input.just_pressed(KeyCode::F2) {
+        visualizer.0 = !visualizer.0;
+    }
+}
+
+fn input_visualizer(
+    input: Res<ButtonInput<KeyCode>>,
+    visualizer: Res<InputVisualizer>,
+    mut roots: Query<&mut Visibility, With<InputVisualizerRoot>>,
+    mut arrows: Query<(&InputArrow, &mut BackgroundColor)>,
+) {
+    for mut visibility in roots.iter_mut() {
+        *visibility = if visualizer.0 {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+    if !visualizer.0 {
+        return;
+    }
+
+    for (arrow, mut background) in arrows.iter_mut() {
+        *background = if input.pressed(arrow.0) {
+            INPUT_ARROW_ACTIVE_COLOR.into()
+        } else {
+            INPUT_ARROW_IDLE_COLOR.into()
+        };
+    }
+}
+
+fn capture_frame(
+    mut capture: ResMut<FrameCapture>,
+    mut screenshots: ResMut<ScreenshotManager>,
+    windows: Query<Entity, With<PrimaryWindow>>,
+) {
+    if !capture.enabled || capture.frame >= FRAME_CAPTURE_MAX {
+        return;
+    }
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+
+    if capture.frame == 0 {
+        if let Err(err) = std::fs::create_dir_all(FRAME_CAPTURE_DIR) {
+            warn!("frame capture disabled, cannot create {FRAME_CAPTURE_DIR}: {err}");
+            capture.enabled = false;
+            return;
+        }
+    }
+
+    let path = format!("{FRAME_CAPTURE_DIR}/frame_{:05}.png", capture.frame);
+    match screenshots.save_screenshot_to_disk(window, path) {
+        Ok(()) => capture.frame += 1,
+        Err(err) => warn!("skipping frame {}: {err}", capture.frame),
+    }
+
+    if capture.frame == FRAME_CAPTURE_MAX {
+        info!("frame capture stopped after {FRAME_CAPTURE_MAX} frames");
+    }
+}
+
+/// Like `position_translation`, rescales only entities whose `Size` changed
+/// unless the window was resized.
+fn size_scaling(
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut resized: EventReader<WindowResized>,
+    mut q: Query<(Ref<Size>, &mut Transform)>,
+) {
+    let full_pass = resized.read().count() > 0;
+    if let Ok(window) = windows.get_single() {
+        for (sprite_size, mut transform) in q.iter_mut() {
+            if !full_pass && !sprite_size.is_changed() {
+                continue;
+            }
+            transform.scale = Vec3::new(
+                sprite_size.width / ARENA_WIDTH as f32 * window.width(),
+                sprite_size.height / ARENA_HEIGHT as f32 * window.height(),
+                1.0,
+            );
+        }
+    }
+}
+
+fn snake_sprite_index(ahead: Option<Position>, pos: Position, behind: Option<Position>) -> usize {
+    let to_ahead = ahead.and_then(|p| pos.direction_to(p));
+    let to_behind = behind.and_then(|p| pos.direction_to(p));
+    match (to_ahead, to_behind) {
+        (None, Some(back)) => back.opposite().index(),
+        (Some(front), None) => 4 + front.index(),
+        (Some(front), Some(back)) if front == back.opposite() => match front {
+            Direction::Up | Direction::Down => 8,
+            Direction::Left | Direction::Right => 9,
+        },
+        (Some(front), Some(back)) => {
+            let (a, b) = if front.index() < back.index() {
+                (front.index(), back.index())
+            } else {
+                (back.index(), front.index())
+            };
+            if (a, b) == (0, 3) {
+                13
+            } else {
+                10 + a
+            }
+        }
+        (None, None) => 0,
+    }
+}
+
+/// Swaps the snake's coloured squares for sprite-sheet pieces once the sheet
+/// has loaded. If it never loads, the squares are left as they are.
+fn apply_snake_sprites(
+    mut commands: Commands,
+    theme: Res<Theme>,
+    sprites: Option<Res<SnakeSprites>>,
+    asset_server: Res<AssetServer>,
+    segments: Res<SnakeSegments>,
+    positions: Query<&Position>,
+    mut appearance: Query<(&mut Sprite, &mut Handle<Image>, Option<&mut TextureAtlas>)>,
+) {
+    let Some(sprites) = sprites else {
+        return;
+    };
+    if !theme.animated() {
+        // Flat blocks only: strip the sheet from anything still wearing it.
+        for &entity in segments.0.iter() {
+            if let Ok((mut sprite, mut texture, Some(_))) = appearance.get_mut(entity) {
+                sprite.custom_size = None;
+                *texture = Handle::default();
+                commands.entity(entity).remove::<TextureAtlas>();
+            }
+        }
+        return;
+    }
+    if asset_server.get_load_state(&sprites.image) != Some(LoadState::Loaded) {
+        return;
+    }
+
+    let cells: Vec<Position> = segments
+        .0
+        .iter()
+        .filter_map(|&e| positions.get(e).ok().copied())
+        .collect();
+    if cells.len() != segments.0.len() {
+        return;
+    }
+
+    for (i, &entity) in segments.0.iter().enumerate() {
+        let index = snake_sprite_index(
+            i.checked_sub(1).map(|ahead| cells[ahead]),
+            cells[i],
+            cells.get(i + 1).copied(),
+        );
+        let Ok((mut sprite, mut texture, atlas)) = appearance.get_mut(entity) else {
+            continue;
+        };
+        sprite.color = Color::WHITE;
+        sprite.custom_size = Some(Vec2::ONE);
+        *texture = sprites.image.clone();
+        match atlas {
+            Some(mut atlas) => atlas.index = index,
+            None => {
+                commands.entity(entity).insert(TextureAtlas {
+                    layout: sprites.layout.clone(),
+                    index,
+                });
+            }
+        }
+    }
+}
+
+fn mix_color(from: Color, to: Color, t: f32) -> Color {
+    Color::rgba(
+        from.r() + (to.r() - from.r()) * t,
+        from.g() + (to.g() - from.g()) * t,
+        from.b() + (to.b() - from.b()) * t,
+        from.a() + (to.a() - from.a()) * t,
+    )
+}
+
+/// Paints the snake with its base palette, tinted by the current speed. The
+/// head flashes while wall grace is holding it at the edge.
+fn snake_coloring(
+    tint: Res<SpeedTint>,
+    theme: Res<Theme>,
+    grace: Res<WallGrace>,
+    effect: Res<ActiveEffect>,
+    time: Res<Time>,
+    timer: Res<MovementTimer>,
+    mut segments: Query<(&mut Sprite, Has<SnakeHead>, Has<TextureAtlas>), With<SnakeSegment>>,
+) {
+    let speed_up = if tint.0 && theme.animated() {
+        let interval = timer.0.duration().as_secs_f32();
+        ((MOVEMENT_INTERVAL - interval) / (MOVEMENT_INTERVAL - SPEED_TINT_FASTEST_INTERVAL))
+            .clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+
+    for (mut sprite, is_head, textured) in segments.iter_mut() {
+        let base = if textured {
+            Color::WHITE
+        } else if is_head {
+            theme.head()
+        } else {
+            theme.segment()
+        };
+        sprite.color = mix_color(base, SPEED_TINT_COLOR, speed_up * SPEED_TINT_STRENGTH);
+        if is_head
+            && grace.holding
+            && (time.elapsed_seconds() * WALL_GRACE_FLASHES_PER_SECOND) as u32 % 2 == 0
+        {
+            sprite.color = WALL_GRACE_COLOR;
+        }
+        if effect.is(PowerUp::Shield)
+            && (time.elapsed_seconds() * SHIELD_FLASHES_PER_SECOND) as u32 % 2 == 0
+        {
+            sprite.color.set_a(0.3);
+        }
+    }
+}
+
+/// Pulses the whole snake towards the power-up color while an effect is
+/// running. Applied over `snake_coloring`, so it wins over the speed tint,
+/// but it keeps the alpha so the shield still flashes.
+fn effect_glow(
+    effect: Res<ActiveEffect>,
+    theme: Res<Theme>,
+    time: Res<Time>,
+    mut segments: Query<&mut Sprite, With<SnakeSegment>>,
+) {
+    if effect.0.is_none() {
+        return;
+    }
+    // Themes without animation get a steady glow instead of a pulse.
+    let pulse = if theme.animated() {
+        let turns = time.elapsed_seconds() * EFFECT_GLOW_PULSES_PER_SECOND;
+        (turns * std::f32::consts::TAU).sin() * 0.5 + 0.5
+    } else {
+        0.5
+    };
+    for mut sprite in segments.iter_mut() {
+        let alpha = sprite.color.a();
+        sprite.color = mix_color(sprite.color, POWER_UP_COLOR, pulse * EFFECT_GLOW_STRENGTH);
+        sprite.color.set_a(alpha);
+    }
+}
+
+/// Paints food and walls with the current theme.
+fn board_coloring(
+    theme: Res<Theme>,
+    challenge: Res<ColorChallenge>,
+    mut food: Query<(&mut Sprite, &FoodColor, &FoodTier), Without<Wall>>,
+    mut walls: Query<&mut Sprite, With<Wall>>,
+) {
+    for (mut sprite, color, tier) in food.iter_mut() {
+        let base = if challenge.enabled {
+            color.color()
+        } else {
+            tier.color()
+        };
+        sprite.color = theme.food(base);
+    }
+    for mut sprite in walls.iter_mut() {
+        sprite.color = theme.wall();
+    }
+}
+
+fn toggle_screen_shake(input: Res<ButtonInput<KeyCode>>, mut shake: ResMut<ScreenShake>) {
+    if input.just_pressed(KeyCode::F6) {
+        shake.intensity = if shake.intensity >= SHAKE_INTENSITY {
+            SHAKE_INTENSITY / 2.0
+        } else if shake.intensity > 0.0 {
+            0.0
+        } else {
+            SHAKE_INTENSITY
+        };
+        write_setting("shake_intensity", &shake.intensity.to_string());
+    }
+}
+
+/// Jolts the camera on a game over, easing back to the center over the
+/// shake's duration. The jolt comes from `thread_rng` rather than `GameRng`,
+/// so turning the shake on doesn't change the food a run code's seed spawns.
+fn screen_shake(
+    time: Res<Time>,
+    shake: Res<ScreenShake>,
+    mut events: EventReader<GameEvent>,
+    mut active: Local<Option<Timer>>,
+    mut cameras: Query<&mut Transform, With<Camera2d>>,
+) {
+    let died = events
+        .read()
+        .filter(|event| **event == GameEvent::GameOver)
+        .count();
+    if died > 0 && shake.enabled() {
+        *active = Some(Timer::from_seconds(shake.duration, TimerMode::Once));
+    }
+
+    let mut offset = Vec2::ZERO;
+    if let Some(timer) = active.as_mut() {
+        if timer.tick(time.delta()).finished() || !shake.enabled() {
+            *active = None;
+        } else {
+            let mut rng = rand::thread_rng();
+            let jolt = Vec2::new(rng.gen_range(-1.0..=1.0), rng.gen_range(-1.0..=1.0));
+            offset = jolt * shake.intensity * (1.0 - timer.fraction());
+        }
+    }
+    for mut transform in cameras.iter_mut() {
+        if transform.translation.truncate() != offset {
+            transform.translation.x = offset.x;
+            transform.translation.y = offset.y;
+        }
+    }
+}
+
+fn toggle_theme(input: Res<ButtonInput<KeyCode>>, mut theme: ResMut<Theme>) {
+    if input.just_pressed(KeyCode::F5) {
+        *theme = match *theme {
+            Theme::Classic => Theme::Nokia,
+            Theme::Nokia => Theme::Classic,
+        };
+        write_setting("theme", theme.name());
+    }
+}
+
+fn apply_theme(
+    theme: Res<Theme>,
+    mut clear_color: ResMut<ClearColor>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    backgrounds: Query<&Mesh2dHandle, With<Background>>,
+) {
+    clear_color.0 = theme.background();
+
+    // `Rectangle` vertices run top-right, top-left, bottom-left, bottom-right.
+    let [top, bottom] = theme.gradient().map(|color| color.as_linear_rgba_f32());
+    for handle in backgrounds.iter() {
+        if let Some(mesh) = meshes.get_mut(&handle.0) {
+            mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, vec![top, top, bottom, bottom]);
+        }
+    }
+}
+
+fn trap_weight(pos: Position, tail: Option<Position>) -> u32 {
+    let on_x_edge = pos.x == 0 || pos.x == ARENA_WIDTH as i32 - 1;
+    let on_y_edge = pos.y == 0 || pos.y == ARENA_HEIGHT as i32 - 1;
+
+    let mut weight = 1;
+    if on_x_edge || on_y_edge {
+        weight += TRAP_EDGE_WEIGHT;
+    }
+    if on_x_edge && on_y_edge {
+        weight += TRAP_CORNER_WEIGHT;
+    }
+    if tail.is_some_and(|tail| (pos.x - tail.x).abs() + (pos.y - tail.y).abs() <= TRAP_TAIL_RADIUS)
+    {
+        weight += TRAP_TAIL_WEIGHT;
+    }
+    weight
+}
+
+/// Walls off the next ring in from the edge whenever the shrink timer fires.
+/// Cells under the snake are spared so it isn't buried alive, but food caught
+/// in the ring is lost.
+fn shrink_arena(
+    mut command: Commands,
+    time: Res<Time>,
+    mut arena: ResMut<ShrinkingArena>,
+    snake: Query<&Position, With<SnakeSegment>>,
+    walls: Query<&Position, With<Wall>>,
+    food: Query<(Entity, &Position), With<Food>>,
+) {
+    let fired = arena
+        .timer
+        .as_mut()
+        .is_some_and(|timer| timer.tick(time.delta()).just_finished());
+    if !fired || arena.rings * 2 >= ARENA_WIDTH.min(ARENA_HEIGHT) as i32 {
+        return;
+    }
+
+    let ring = arena.rings;
+    let on_ring = |pos: &Position| {
+        let from_edge = pos
+            .x
+            .min(pos.y)
+            .min(ARENA_WIDTH as i32 - 1 - pos.x)
+            .min(ARENA_HEIGHT as i32 - 1 - pos.y);
+        from_edge == ring
+    };
+    let snake: Vec<Position> = snake.iter().copied().collect();
+    let walled: Vec<Position> = walls.iter().copied().collect();
+    for cell in arena_cells().filter(on_ring) {
+        if !snake.contains(&cell) && !walled.contains(&cell) {
+            let wall = spawn_wall(&mut command, cell);
+            command.entity(wall).insert(RunWall);
+        }
+    }
+    for (entity, pos) in food.iter().filter(|(_, pos)| on_ring(pos)) {
+        command.entity(entity).despawn();
+    }
+    arena.rings += 1;
+}
+
+/// Steps each food to the free neighbouring cell furthest from the head. Runs
+/// after eating, so food the head just reached can't slip away.
+fn flee_food(
+    ticks: Res<TickCount>,
+    heads: Query<&Position, (With<SnakeHead>, Without<Food>)>,
+    occupied: Query<&Position, (Or<(With<SnakeSegment>, With<Wall>)>, Without<Food>)>,
+    mut food: Query<&mut Position, With<Food>>,
+) {
+    if ticks.0 % PREY_STEP_TICKS != 0 {
+        return;
+    }
+    let Some(&head) = heads.iter().next() else {
+        return;
+    };
+    let mut taken: Vec<Position> = occupied.iter().copied().collect();
+    taken.extend(food.iter().copied());
+
+    for mut pos in food.iter_mut() {
+        let here = *pos;
+        let escape = Direction::ALL
+            .into_iter()
+            .map(|direction| here.step(direction))
+            .filter(|next| in_arena(*next) && !taken.contains(next))
+            .filter(|next| next.manhattan(head) > here.manhattan(head))
+            .max_by_key(|next| next.manhattan(head));
+        if let Some(next) = escape {
+            taken.retain(|taken| *taken != here);
+            taken.push(next);
+            *pos = next;
+        }
+    }
+}
+
+/// Puts a power-up on a random free cell, unless one is already on the board
+/// or in effect.
+fn power_up_spawner(
+    mut command: Commands,
+    mut rng: ResMut<GameRng>,
+    effect: Res<ActiveEffect>,
+    pickups: Query<(), With<PowerUp>>,
+    occupied: Query<&Position, Or<(With<SnakeSegment>, With<Food>, With<Wall>)>>,
+) {
+    if effect.0.is_some() || !pickups.is_empty() {
+        return;
+    }
+    let occupied: Vec<Position> = occupied.iter().copied().collect();
+    let free: Vec<Position> = arena_cells()
+        .filter(|pos| !occupied.contains(pos))
+        .collect();
+    let Some(&position) = free.choose(&mut rng.0) else {
+        return;
+    };
+    command.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: POWER_UP_COLOR,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        PowerUp::BigHead,
+        GameWorld,
+        position,
+        Size::square(0.6),
+    ));
+}
+
+fn collect_power_ups(
+    mut command: Commands,
+    mut effect: ResMut<ActiveEffect>,
+    heads: Query<(&SnakeHead, &Position)>,
+    pickups: Query<(Entity, &PowerUp, &Position)>,
+) {
+    let Some((head, head_pos)) = heads.iter().next() else {
+        return;
+    };
+    let covered = effect.head_cells(*head_pos, head.direction);
+    for (entity, &power_up, pos) in pickups.iter() {
+        if covered.contains(pos) {
+            command.entity(entity).despawn();
+            let timer = Timer::from_seconds(power_up.duration(), TimerMode::Once);
+            effect.0 = Some((power_up, timer));
+        }
+    }
+}
+
+fn tick_active_effect(time: Res<Time>, mut effect: ResMut<ActiveEffect>) {
+    let expired = effect
+        .0
+        .as_mut()
+        .is_some_and(|(_, timer)| timer.tick(time.delta()).finished());
+    if expired {
+        effect.0 = None;
+    }
+}
+
+/// Draws a big head over its whole footprint, and shrinks it back once the
+/// effect ends.
+fn big_head(
+    effect: Res<ActiveEffect>,
+    mut heads: Query<(&SnakeHead, &mut Size, &mut RenderOffset)>,
+) {
+    let big = effect.is(PowerUp::BigHead);
+    for (head, mut size, mut offset) in heads.iter_mut() {
+        let (side, centre) = if big {
+            let toward = head.direction.unit() + head.direction.rotated_right().unit();
+            (BIG_HEAD_SIZE, toward / 2.0)
+        } else {
+            (HEAD_SIZE, Vec2::ZERO)
+        };
+        if size.width != side {
+            *size = Size::square(side);
+        }
+        offset.set_if_neq(RenderOffset(centre));
+    }
+}
+
+fn food_spawner(
+    mut command: Commands,
+    avoid_heading: Res<FoodAvoidsHeading>,
+    challenge: Res<ColorChallenge>,
+    trap: Res<TrapSpawn>,
+    tiers: Res<FoodTierWeights>,
+    splitting: Res<SplittingFood>,
+    mut rng: ResMut<GameRng>,
+    segments: Res<SnakeSegments>,
+    heads: Query<(&SnakeHead, &Position)>,
+    occupied: Query<&Position, Or<(With<SnakeSegment>, With<Food>, With<Wall>, With<PowerUp>)>>,
+) {
+    let tail = segments
+        .0
+        .last()
+        .and_then(|&e| occupied.get(e).ok().copied());
+    let occupied: Vec<Position> = occupied.iter().copied().collect();
+    let free: Vec<Position> = arena_cells()
+        .filter(|pos| !occupied.contains(pos))
+        .collect();
+
+    let mut candidates = free.clone();
+    if avoid_heading.0 {
+        if let Some((head, head_pos)) = heads.iter().next() {
+            let mut ahead = *head_pos;
+            for _ in 0..FOOD_HEADING_GUARD {
+                ahead = ahead.step(head.direction);
+                candidates.retain(|pos| *pos != ahead);
+            }
+        }
+    }
+
+    // Excluding the heading can leave nothing on a crowded board, so any free
+    // cell is better than no food at all.
+    if candidates.is_empty() {
+        candidates = free;
+    }
+    if candidates.is_empty() {
+        return;
+    }
+
+    let rng = &mut rng.0;
+    let color = if challenge.enabled && rng.gen() {
+        challenge.target.other()
+    } else if challenge.enabled {
+        challenge.target
+    } else {
+        FoodColor::Magenta
+    };
+
+    let tier = tiers.pick(rng);
+
+    let position = if trap.0 {
+        *candidates
+            .choose_weighted(rng, |&pos| trap_weight(pos, tail))
+            .unwrap()
+    } else {
+        candidates[rng.gen_range(0..candidates.len())]
+    };
+
+    let food = spawn_food(&mut command, position, tier, color);
+    if rng.gen_bool(FLICKER_FOOD_CHANCE) {
+        command.entity(food).insert(FlickerFood);
+    }
+    if splitting.0 && rng.gen_bool(SPLITTER_FOOD_CHANCE) {
+        command
+            .entity(food)
+            .insert((SplitterFood, Size::square(0.6)));
+    }
+}
+
+/// Leaves two common foods on the free cells nearest an eaten `SplitterFood`,
+/// as far as `MAX_FOOD` and the board allow.
+fn split_food(
+    mut command: Commands,
+    mut events: EventReader<GameEvent>,
+    challenge: Res<ColorChallenge>,
+    mut rng: ResMut<GameRng>,
+    food: Query<(), With<Food>>,
+    occupied: Query<&Position, Or<(With<SnakeSegment>, With<Food>, With<Wall>, With<PowerUp>)>>,
+) {
+    let mut food_count = food.iter().count();
+    let mut occupied: Vec<Position> = occupied.iter().copied().collect();
+    let color = if challenge.enabled {
+        challenge.target
+    } else {
+        FoodColor::Magenta
+    };
+    for event in events.read() {
+        let GameEvent::Split(at) = *event else {
+            continue;
+        };
+        let mut free: Vec<Position> = arena_cells()
+            .filter(|pos| !occupied.contains(pos))
+            .collect();
+        // Shuffle first so ties in distance don't always favour the same side.
+        free.shuffle(&mut rng.0);
+        free.sort_by_key(|pos| pos.manhattan(at));
+        for pos in free.into_iter().take(2) {
+            if food_count >= MAX_FOOD {
+                return;
+            }
+            spawn_food(&mut command, pos, FoodTier::Common, color);
+            occupied.push(pos);
+            food_count += 1;
+        }
+    }
+}
+
+fn spawn_food(
+    command: &mut Commands,
+    position: Position,
+    tier: FoodTier,
+    color: FoodColor,
+) -> Entity {
+    command
+        .spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: tier.color(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            tier.food(),
+            tier,
+            color,
+            GameWorld,
+            position,
+            Size::square(0.8),
+        ))
+        .id()
+}
+
+/// Maps grid cells to window coordinates. Built once per frame so the tile
+/// size and origin aren't recomputed for every entity.
+struct Grid {
+    tile: Vec2,
+    origin: Vec2,
+}
+
+impl Grid {
+    fn new(window: &Window) -> Self {
+        let size = Vec2::new(window.width(), window.height());
+        let tile = size / Vec2::new(ARENA_WIDTH as f32, ARENA_HEIGHT as f32);
+        Self {
+            tile,
+            origin: tile / 2.0 - size / 2.0,
+        }
+    }
+
+    fn cell_center(&self, pos: Position) -> Vec3 {
+        (self.origin + Vec2::new(pos.x as f32, pos.y as f32) * self.tile).extend(0.0)
+    }
+}
+
+/// Only entities whose `Position` or `RenderOffset` changed are moved, except
+/// after a window resize, which shifts every cell and forces a full pass.
+///
+/// Off-board positions are drawn on the nearest edge rather than off-screen,
+/// so position bugs stay visible; `SNAKE_DEBUG` also logs them.
+fn position_translation(
+    debug: Res<DebugMode>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut resized: EventReader<WindowResized>,
+    mut q: Query<(
+        Entity,
+        Ref<Position>,
+        Option<Ref<RenderOffset>>,
+        &mut Transform,
+    )>,
+) {
+    let full_pass = resized.read().count() > 0;
+    if let Ok(window) = windows.get_single() {
+        let grid = Grid::new(window);
+        for (ent, pos, offset, mut transform) in q.iter_mut() {
+            let offset_changed = offset.as_ref().is_some_and(|offset| offset.is_changed());
+            if !full_pass && !pos.is_changed() && !offset_changed {
+                continue;
+            }
+
+            let mut cell = *pos;
+            if !in_arena(cell) {
+                if debug.0 {
+                    warn!(
+                        "{:?} is off the board at ({}, {}), drawing it on the edge",
+                        ent, cell.x, cell.y
+                    );
+                }
+                cell.x = cell.x.clamp(0, ARENA_WIDTH as i32 - 1);
+                cell.y = cell.y.clamp(0, ARENA_HEIGHT as i32 - 1);
+            }
+            let offset = offset.map_or(Vec2::ZERO, |offset| offset.0 * grid.tile);
+            transform.translation = grid.cell_center(cell) + offset.extend(0.0);
+        }
+    }
+}
+
+/// Sways body segments sideways to their direction of travel, phase-shifted
+/// along the body. Only the drawn transform moves; `Position` is untouched.
+fn wiggle_segments(
+    time: Res<Time>,
+    wiggle: Res<Wiggle>,
+    spacing: Res<SegmentSpacing>,
+    theme: Res<Theme>,
+    segments: Res<SnakeSegments>,
+    positions: Query<&Position>,
+    mut offsets: Query<&mut RenderOffset>,
+) {
+    let amplitude = if theme.animated() { wiggle.0 } else { 0.0 };
+    for (i, pair) in segments.0.windows(2).enumerate() {
+        let (Ok(ahead), Ok(pos)) = (positions.get(pair[0]), positions.get(pair[1])) else {
+            continue;
+        };
+        let Ok(mut offset) = offsets.get_mut(pair[1]) else {
+            continue;
+        };
+
+        let (sideways, forward) = match pos.direction_to(*ahead) {
+            Some(Direction::Left) => (Vec2::Y, Vec2::NEG_X),
+            Some(Direction::Right) => (Vec2::Y, Vec2::X),
+            Some(Direction::Up) => (Vec2::X, Vec2::Y),
+            Some(Direction::Down) => (Vec2::X, Vec2::NEG_Y),
+            None => (Vec2::ZERO, Vec2::ZERO),
+        };
+        let phase = time.elapsed_seconds() * WIGGLE_SPEED - (i + 1) as f32 * WIGGLE_PHASE_STEP;
+        offset.set_if_neq(RenderOffset(
+            sideways * amplitude * phase.sin() + forward * spacing.0,
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+
+    /// A world with every resource the gameplay systems read, at its default.
+    fn world() -> World {
+        let mut world = World::new();
+        world.init_resource::<Events<GameEvent>>();
+        world.init_resource::<Time>();
+        world.init_resource::<ButtonInput<KeyCode>>();
+        world.init_resource::<NextState<GameState>>();
+        world.insert_resource(FontHandle(Handle::default()));
+        world.insert_resource(MaxEffects(MAX_EFFECTS));
+        world.insert_resource(GameRng::new(0));
+        world.init_resource::<RunParams>();
+        world.init_resource::<SnakeSegments>();
+        world.init_resource::<LastTailPosition>();
+        world.init_resource::<Start>();
+        world.init_resource::<RespawnPolicy>();
+        world.init_resource::<SpawnProtection>();
+        world.init_resource::<DebugMode>();
+        world.init_resource::<SystemToggles>();
+        world.init_resource::<Score>();
+        world.init_resource::<Streak>();
+        world.init_resource::<StreakBonus>();
+        world.init_resource::<LastRegisteredDir>();
+        world.init_resource::<InputBuffer>();
+        world.init_resource::<LengthGoal>();
+        world.init_resource::<RunClock>();
+        world.init_resource::<RunPath>();
+        world.init_resource::<DeathSlowMo>();
+        world.init_resource::<DeathAnimation>();
+        world.init_resource::<CodeEntry>();
+        world.init_resource::<TurnsSinceEat>();
+        world.init_resource::<DashPending>();
+        world.init_resource::<DashedOver>();
+        world.init_resource::<TailSevering>();
+        world.init_resource::<WallGrace>();
+        world.init_resource::<GrowDelay>();
+        world.init_resource::<PendingGrowth>();
+        world.init_resource::<MovementTimer>();
+        world.init_resource::<TickCount>();
+        world.init_resource::<ConstantSpeed>();
+        world.init_resource::<AutoBalance>();
+        world.init_resource::<LifeStats>();
+        world.init_resource::<ColorChallenge>();
+        world.init_resource::<ControlScheme>();
+        world.init_resource::<KeyTrigger>();
+        world.init_resource::<ScreenShake>();
+        world.init_resource::<InvertControls>();
+        world.init_resource::<MovementStyle>();
+        world.init_resource::<ShrinkingArena>();
+        world.init_resource::<SplittingFood>();
+        world.init_resource::<ScoringMode>();
+        world.init_resource::<FoodAvoidsHeading>();
+        world.init_resource::<CorpseWalls>();
+        world.init_resource::<TrapSpawn>();
+        world.init_resource::<FoodTierWeights>();
+        world.init_resource::<EatRadius>();
+        world.init_resource::<ActiveEffect>();
+        world
+    }
+
+    fn pos(x: i32, y: i32) -> Position {
+        Position { x, y }
+    }
+
+    /// Lays a snake out on `cells`, head first, through `spawn_snake_from`.
+    fn try_spawn(
+        world: &mut World,
+        cells: &[Position],
+        direction: Direction,
+    ) -> Result<(), String> {
+        let cells = cells.to_vec();
+        world.run_system_once(
+            move |mut commands: Commands, mut segments: ResMut<SnakeSegments>| {
+                spawn_snake_from(&mut commands, &mut segments, &cells, direction)
+            },
+        )
+    }
+
+    fn spawn_at(world: &mut World, cells: &[Position], direction: Direction) {
+        try_spawn(world, cells, direction).unwrap();
+    }
+
+    fn add_food(world: &mut World, at: Position) -> Entity {
+        world.run_system_once(move |mut commands: Commands| {
+            spawn_food(&mut commands, at, FoodTier::Common, FoodColor::Magenta)
+        })
+    }
+
+    fn add_wall(world: &mut World, at: Position) -> Entity {
+        world.run_system_once(move |mut commands: Commands| spawn_wall(&mut commands, at))
+    }
+
+    fn count<T: Component>(world: &mut World) -> usize {
+        world.query_filtered::<(), With<T>>().iter(world).count()
+    }
+
+    fn sent(world: &World, event: GameEvent) -> usize {
+        let events = world.resource::<Events<GameEvent>>();
+        events
+            .get_reader()
+            .read(events)
+            .filter(|&&sent| sent == event)
+            .count()
+    }
+
+    /// The snake's cells, head first.
+    fn body(world: &World) -> Vec<Position> {
+        world
+            .resource::<SnakeSegments>()
+            .0
+            .iter()
+            .map(|&segment| *world.get::<Position>(segment).unwrap())
+            .collect()
+    }
+
+    fn press(world: &mut World, key: KeyCode) {
+        world.resource_mut::<ButtonInput<KeyCode>>().press(key);
+    }
+
+    fn release(world: &mut World, key: KeyCode) {
+        world.resource_mut::<ButtonInput<KeyCode>>().release(key);
+    }
+
+    /// Samples the input once, then starts the next frame: anything pressed
+    /// stays held, but no longer `just_pressed`.
+    fn input_frame(world: &mut World) {
+        world.run_system_once(snake_input_moviment);
+        world.resource_mut::<ButtonInput<KeyCode>>().clear();
+    }
+
+    fn queued(world: &World) -> Vec<Direction> {
+        world.resource::<InputBuffer>().0.iter().copied().collect()
+    }
+
+    fn heading(world: &mut World) -> Direction {
+        world.query::<&SnakeHead>().single(world).direction
+    }
+
+    /// Ends the run the way a fatal move does.
+    fn die(world: &mut World) {
+        world
+            .resource_mut::<Events<GameEvent>>()
+            .send(GameEvent::GameOver);
+        world.run_system_once(game_over);
+    }
+
+    fn settings(key: &str, value: &str) -> Settings {
+        Settings(HashMap::from([(key.to_string(), value.to_string())]))
+    }
+
+    fn big_head() -> ActiveEffect {
+        ActiveEffect(Some((
+            PowerUp::BigHead,
+            Timer::from_seconds(BIG_HEAD_SECONDS, TimerMode::Once),
+        )))
+    }
+
+    #[test]
+    fn despawn_game_world_clears_the_run_but_keeps_walls() {
+        let mut world = world();
+        spawn_at(&mut world, &[pos(5, 5), pos(5, 4)], Direction::Up);
+        add_food(&mut world, pos(1, 1));
+        add_wall(&mut world, pos(2, 2));
+
+        world.run_system_once(
+            |mut commands: Commands, run: Query<Entity, With<GameWorld>>| {
+                despawn_game_world(&mut commands, &run);
+            },
+        );
+
+        assert_eq!(count::<GameWorld>(&mut world), 0);
+        // The head's motion blur streak goes with it.
+        assert_eq!(count::<MotionBlurStreak>(&mut world), 0);
+        assert_eq!(count::<Wall>(&mut world), 1);
+    }
+
+    #[test]
+    fn grazing_the_tail_tip_earns_the_bonus() {
+        let mut world = world();
+        let snake = [pos(2, 2), pos(2, 3), pos(1, 3), pos(1, 2)];
+        spawn_at(&mut world, &snake, Direction::Left);
+
+        world.run_system_once(snake_movement);
+
+        assert_eq!(world.resource::<Score>().0, TAIL_GRAZE_BONUS);
+        assert_eq!(sent(&world, GameEvent::GameOver), 0);
+    }
+
+    #[test]
+    fn biting_the_body_behind_the_tip_is_fatal() {
+        let mut world = world();
+        let snake = [pos(2, 2), pos(2, 3), pos(1, 3), pos(1, 2), pos(1, 1)];
+        spawn_at(&mut world, &snake, Direction::Left);
+
+        world.run_system_once(snake_movement);
+
+        assert_eq!(world.resource::<Score>().0, 0);
+        assert_eq!(sent(&world, GameEvent::GameOver), 1);
+    }
+
+    #[test]
+    fn sprite_index_picks_the_piece_for_each_neighbour_pair() {
+        let at = pos(5, 5);
+        let toward = |direction: Direction| Some(at.step(direction));
+
+        for direction in Direction::ALL {
+            // The head faces away from the segment behind it.
+            let head = snake_sprite_index(None, at, toward(direction.opposite()));
+            assert_eq!(head, direction.index());
+            let tail = snake_sprite_index(toward(direction), at, None);
+            assert_eq!(tail, 4 + direction.index());
+        }
+
+        let straight = |a, b| snake_sprite_index(toward(a), at, toward(b));
+        assert_eq!(straight(Direction::Up, Direction::Down), 8);
+        assert_eq!(straight(Direction::Down, Direction::Up), 8);
+        assert_eq!(straight(Direction::Left, Direction::Right), 9);
+        assert_eq!(straight(Direction::Right, Direction::Left), 9);
+
+        // Corners don't care which side is ahead.
+        for (a, b, index) in [
+            (Direction::Up, Direction::Right, 10),
+            (Direction::Right, Direction::Down, 11),
+            (Direction::Down, Direction::Left, 12),
+            (Direction::Left, Direction::Up, 13),
+        ] {
+            assert_eq!(straight(a, b), index);
+            assert_eq!(straight(b, a), index);
+        }
+    }
+
+    #[test]
+    fn food_only_spawns_on_free_cells_inside_the_arena() {
+        let mut world = world();
+        spawn_at(&mut world, &[pos(5, 5), pos(5, 4)], Direction::Up);
+        add_wall(&mut world, pos(0, 0));
+
+        // More runs than there are cells, so the board fills up.
+        for _ in 0..300 {
+            world.run_system_once(food_spawner);
+        }
+
+        let food: Vec<Position> = world
+            .query_filtered::<&Position, With<Food>>()
+            .iter(&world)
+            .copied()
+            .collect();
+        let cells: HashSet<Position> = food.iter().copied().collect();
+        assert_eq!(cells.len(), food.len());
+        assert_eq!(food.len(), (ARENA_WIDTH * ARENA_HEIGHT) as usize - 3);
+        assert!(food.iter().all(|&cell| in_arena(cell)));
+        for taken in [pos(5, 5), pos(5, 4), pos(0, 0)] {
+            assert!(!cells.contains(&taken));
+        }
+    }
+
+    #[test]
+    fn severing_cuts_the_snake_at_the_bite() {
+        let mut world = world();
+        world.resource_mut::<TailSevering>().0 = true;
+        let snake = [pos(2, 2), pos(2, 3), pos(1, 3), pos(1, 2), pos(1, 1)];
+        spawn_at(&mut world, &snake, Direction::Left);
+        let cut = world.resource::<SnakeSegments>().0[4];
+
+        world.run_system_once(snake_movement);
+
+        assert_eq!(sent(&world, GameEvent::GameOver), 0);
+        assert_eq!(world.resource::<SnakeSegments>().0.len(), 4);
+        assert!(world.get_entity(cut).is_none());
+        assert_eq!(count::<SnakeSegment>(&mut world), 4);
+    }
+
+    #[test]
+    fn eaten_food_is_despawned_and_counted_once() {
+        let mut world = world();
+        spawn_at(&mut world, &[pos(5, 5), pos(5, 4)], Direction::Up);
+        add_food(&mut world, pos(5, 6));
+
+        world.run_system_once(snake_movement);
+        world.run_system_once(snake_eating);
+        // A second pass over the same tick finds nothing left to eat.
+        world.run_system_once(snake_eating);
+        world.run_system_once(snake_growth);
+
+        assert_eq!(count::<Food>(&mut world), 0);
+        assert_eq!(sent(&world, GameEvent::Growth), 1);
+        assert_eq!(sent(&world, GameEvent::Ate), 1);
+        assert_eq!(world.resource::<Score>().0, 1);
+        assert_eq!(world.resource::<SnakeSegments>().0.len(), 3);
+    }
+
+    #[test]
+    fn dash_eats_food_on_the_skipped_cell() {
+        let mut world = world();
+        spawn_at(&mut world, &[pos(5, 5), pos(4, 5)], Direction::Right);
+        add_food(&mut world, pos(6, 5));
+        world.resource_mut::<DashPending>().0 = true;
+
+        world.run_system_once(snake_movement);
+        assert_eq!(body(&world), [pos(7, 5), pos(6, 5)]);
+        world.run_system_once(snake_eating);
+
+        assert_eq!(count::<Food>(&mut world), 0);
+        assert_eq!(world.resource::<Score>().0, 1);
+    }
+
+    #[test]
+    fn dash_dies_on_a_wall_in_the_skipped_cell() {
+        let mut world = world();
+        spawn_at(&mut world, &[pos(5, 5), pos(4, 5)], Direction::Right);
+        add_wall(&mut world, pos(6, 5));
+        world.resource_mut::<DashPending>().0 = true;
+
+        world.run_system_once(snake_movement);
+
+        assert_eq!(sent(&world, GameEvent::GameOver), 1);
+    }
+
+    #[test]
+    fn dashed_over_cell_only_lasts_its_tick() {
+        let mut world = world();
+        spawn_at(&mut world, &[pos(5, 5), pos(4, 5)], Direction::Right);
+        world.resource_mut::<DashPending>().0 = true;
+
+        world.run_system_once(snake_movement);
+        assert_eq!(world.resource::<DashedOver>().0, Some(pos(6, 5)));
+        // No snake_eating in between, as with eating toggled off.
+        world.run_system_once(snake_movement);
+        assert_eq!(world.resource::<DashedOver>().0, None);
+    }
+
+    #[test]
+    fn held_key_queues_one_turn() {
+        let mut world = world();
+        spawn_at(&mut world, &[pos(5, 5), pos(5, 4)], Direction::Up);
+
+        press(&mut world, KeyCode::ArrowLeft);
+        for _ in 0..50 {
+            input_frame(&mut world);
+        }
+        assert_eq!(queued(&world), [Direction::Left]);
+
+        release(&mut world, KeyCode::ArrowLeft);
+        press(&mut world, KeyCode::ArrowDown);
+        input_frame(&mut world);
+        assert_eq!(queued(&world), [Direction::Left, Direction::Down]);
+    }
+
+    #[test]
+    fn snake_starts_on_the_configured_cell() {
+        let mut world = world();
+        world.insert_resource(Start {
+            cell: Some(pos(2, 7)),
+            direction: Direction::Right,
+        });
+
+        world.run_system_once(setup_snake);
+
+        assert_eq!(body(&world), [pos(2, 7), pos(1, 7)]);
+        assert_eq!(heading(&mut world), Direction::Right);
+    }
+
+    #[test]
+    fn start_without_room_for_the_tail_falls_back_to_the_center() {
+        let mut world = world();
+        world.insert_resource(Start {
+            cell: Some(pos(0, 3)),
+            direction: Direction::Right,
+        });
+
+        world.run_system_once(setup_snake);
+
+        assert_eq!(body(&world), [arena_center(), pos(4, 5)]);
+        assert_eq!(heading(&mut world), Direction::Right);
+    }
+
+    #[test]
+    fn default_start_is_centered_facing_up() {
+        let mut world = world();
+
+        world.run_system_once(setup_snake);
+
+        // Written against the arena constants rather than 10x10 cells, though
+        // only the compiled-in size is ever exercised.
+        let center = pos(ARENA_WIDTH as i32 / 2, ARENA_HEIGHT as i32 / 2);
+        assert_eq!(arena_center(), center);
+        assert_eq!(body(&world), [center, center.step(Direction::Down)]);
+        assert_eq!(heading(&mut world), Direction::Up);
+        assert!(body(&world).iter().all(|&cell| in_arena(cell)));
+    }
+
+    #[test]
+    fn trap_weight_favours_edges_corners_and_the_tail() {
+        assert_eq!(trap_weight(pos(5, 5), None), 1);
+        assert_eq!(trap_weight(pos(0, 5), None), 1 + TRAP_EDGE_WEIGHT);
+        assert_eq!(trap_weight(pos(5, 9), None), 1 + TRAP_EDGE_WEIGHT);
+        assert_eq!(
+            trap_weight(pos(9, 0), None),
+            1 + TRAP_EDGE_WEIGHT + TRAP_CORNER_WEIGHT
+        );
+        assert_eq!(
+            trap_weight(pos(5, 5), Some(pos(5, 7))),
+            1 + TRAP_TAIL_WEIGHT
+        );
+        assert_eq!(trap_weight(pos(5, 5), Some(pos(6, 7))), 1);
+    }
+
+    #[test]
+    fn safe_respawn_cell_keeps_off_the_border() {
+        assert_eq!(safe_respawn_cell(pos(0, 9)), pos(1, 8));
+        assert_eq!(safe_respawn_cell(pos(9, 0)), pos(8, 1));
+        assert_eq!(safe_respawn_cell(pos(-1, 12)), pos(1, 8));
+        assert_eq!(safe_respawn_cell(pos(4, 6)), pos(4, 6));
+    }
+
+    #[test]
+    fn open_start_finds_the_nearest_cell_that_fits() {
+        assert_eq!(open_start(pos(5, 5), Direction::Up, &[]), Some(pos(5, 5)));
+
+        let start = open_start(pos(5, 5), Direction::Up, &[pos(5, 5)]).unwrap();
+        assert_eq!(start.manhattan(pos(5, 5)), 1);
+        // Its trailing cell has to be open too.
+        assert_ne!(start, pos(5, 6));
+
+        let walled: Vec<Position> = arena_cells().collect();
+        assert_eq!(open_start(pos(5, 5), Direction::Up, &walled), None);
+    }
+
+    #[test]
+    fn respawn_near_death_stays_close_to_the_crash() {
+        let mut world = world();
+        world.insert_resource(RespawnPolicy::NearDeath);
+        spawn_at(&mut world, &[pos(9, 5), pos(8, 5)], Direction::Right);
+
+        die(&mut world);
+
+        assert_eq!(body(&world), [pos(8, 5), pos(8, 4)]);
+    }
+
+    #[test]
+    fn respawn_at_start_ignores_the_crash() {
+        let mut world = world();
+        spawn_at(&mut world, &[pos(9, 5), pos(8, 5)], Direction::Right);
+
+        die(&mut world);
+
+        assert_eq!(body(&world), [arena_center(), pos(5, 4)]);
+    }
+
+    #[test]
+    fn two_queued_turns_play_out_over_two_ticks() {
+        let mut world = world();
+        spawn_at(&mut world, &[pos(5, 5), pos(5, 4)], Direction::Up);
+
+        press(&mut world, KeyCode::ArrowRight);
+        input_frame(&mut world);
+        release(&mut world, KeyCode::ArrowRight);
+        press(&mut world, KeyCode::ArrowUp);
+        input_frame(&mut world);
+        assert_eq!(queued(&world), [Direction::Right, Direction::Up]);
+
+        world.run_system_once(snake_movement);
+        assert_eq!(body(&world), [pos(6, 5), pos(5, 5)]);
+        world.run_system_once(snake_movement);
+        assert_eq!(body(&world), [pos(6, 6), pos(6, 5)]);
+        assert!(queued(&world).is_empty());
+    }
+
+    #[test]
+    fn second_turn_cannot_reverse_the_first() {
+        let mut world = world();
+        spawn_at(&mut world, &[pos(5, 5), pos(5, 4)], Direction::Up);
+
+        press(&mut world, KeyCode::ArrowRight);
+        input_frame(&mut world);
+        release(&mut world, KeyCode::ArrowRight);
+        // Perpendicular to the head, but straight back from the queued turn.
+        press(&mut world, KeyCode::ArrowLeft);
+        input_frame(&mut world);
+
+        assert_eq!(queued(&world), [Direction::Right]);
+    }
+
+    #[test]
+    fn expired_effects_are_despawned() {
+        let mut world = world();
+        let short = world
+            .spawn(Lifetime(Timer::from_seconds(1.0, TimerMode::Once)))
+            .id();
+        let long = world
+            .spawn(Lifetime(Timer::from_seconds(5.0, TimerMode::Once)))
+            .id();
+
+        world
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_secs(2));
+        world.run_system_once(despawn_expired);
+
+        assert!(world.get_entity(short).is_none());
+        assert!(world.get_entity(long).is_some());
+    }
+
+    #[test]
+    fn body_follows_the_head_cell_for_cell() {
+        let mut world = world();
+        let snake = [pos(5, 5), pos(5, 4), pos(5, 3), pos(5, 2)];
+        spawn_at(&mut world, &snake, Direction::Up);
+
+        for turn in [
+            None,
+            Some(Direction::Right),
+            Some(Direction::Down),
+            None,
+            Some(Direction::Right),
+            Some(Direction::Up),
+        ] {
+            world.resource_mut::<InputBuffer>().0.extend(turn);
+            let before = body(&world);
+            world.run_system_once(snake_movement);
+            let after = body(&world);
+
+            assert_eq!(after[0], before[0].step(heading(&mut world)));
+            assert_eq!(after[1..], before[..before.len() - 1]);
+        }
+        assert_eq!(sent(&world, GameEvent::GameOver), 0);
+    }
+
+    #[test]
+    fn eating_on_the_last_free_cell_wins() {
+        let mut world = world();
+        // A serpentine over the whole board; the head sits one cell from the
+        // start of it, and the food on that last free cell.
+        let path: Vec<Position> = (0..ARENA_HEIGHT as i32)
+            .flat_map(|y| {
+                let row = (0..ARENA_WIDTH as i32).map(move |x| pos(x, y));
+                if y % 2 == 0 {
+                    row.collect::<Vec<_>>()
+                } else {
+                    row.rev().collect()
+                }
+            })
+            .collect();
+        spawn_at(&mut world, &path[1..], Direction::Left);
+        add_food(&mut world, path[0]);
+
+        world.run_system_once(snake_movement);
+        world.run_system_once(snake_eating);
+        world.run_system_once(snake_growth);
+
+        assert_eq!(sent(&world, GameEvent::GameOver), 0);
+        assert_eq!(world.resource::<SnakeSegments>().0.len(), path.len());
+        assert_eq!(
+            world.resource::<NextState<GameState>>().0,
+            Some(GameState::Won)
+        );
+    }
+
+    #[test]
+    fn constant_speed_overrides_auto_balance() {
+        let intervals = |constant: ConstantSpeed| {
+            let mut world = world();
+            world.resource_mut::<AutoBalance>().0 = true;
+            world.insert_resource(constant);
+            [10, 300, 700].map(|tick| {
+                world.resource_mut::<TickCount>().0 = tick;
+                world.run_system_once(auto_balance);
+                world.run_system_once(pin_speed);
+                world.resource::<MovementTimer>().0.duration().as_secs_f32()
+            })
+        };
+
+        let balanced = intervals(ConstantSpeed::default());
+        assert!(balanced[0] > balanced[1] && balanced[1] > balanced[2]);
+
+        let pinned = intervals(ConstantSpeed {
+            enabled: true,
+            interval: HARDCORE_INTERVAL,
+        });
+        for interval in pinned {
+            assert!((interval - HARDCORE_INTERVAL).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn constant_speed_settings_are_read_and_range_checked() {
+        let mut on = settings("constant_speed", "on");
+        on.0.insert("constant_speed_interval".to_string(), "0.1".to_string());
+        let constant = ConstantSpeed::from_settings(&on).unwrap();
+        assert!(constant.enabled);
+        assert!((constant.interval - 0.1).abs() < 1e-6);
+
+        let off = ConstantSpeed::from_settings(&Settings::default()).unwrap();
+        assert!(!off.enabled);
+        assert_eq!(off.interval, MOVEMENT_INTERVAL);
+
+        let interval =
+            |value| ConstantSpeed::from_settings(&settings("constant_speed_interval", value));
+        assert!(interval("0").is_err());
+        assert!(interval("2").is_err());
+        assert!(interval("fast").is_err());
+        assert!(ConstantSpeed::from_settings(&settings("constant_speed", "always")).is_err());
+    }
+
+    #[test]
+    fn auto_balance_speeds_up_with_long_lives() {
+        let mut world = world();
+        world.resource_mut::<AutoBalance>().0 = true;
+
+        world.resource_mut::<TickCount>().0 = AUTO_BALANCE_TARGET_TICKS as u64;
+        world.run_system_once(auto_balance);
+
+        let interval = world.resource::<MovementTimer>().0.duration();
+        assert!((interval.as_secs_f32() - AUTO_BALANCE_FASTEST).abs() < 1e-4);
+    }
+
+    #[test]
+    fn run_code_round_trips() {
+        let params = RunParams {
+            seed: 0xDEAD_BEEF,
+            corpse_walls: true,
+            trap_spawn: false,
+            avoid_heading: true,
+            tail_severing: true,
+        };
+        let code = encode_run(&params);
+
+        assert!(decode_run(&code) == Some(params));
+        assert!(decode_run(&code.to_lowercase()) == Some(params));
+        assert!(decode_run(&format!(" {code}\n")) == Some(params));
+    }
+
+    #[test]
+    fn run_code_rejects_junk() {
+        assert!(decode_run("not a code!").is_none());
+        // Overflows a u64 long before it ends.
+        assert!(decode_run(&"Z".repeat(40)).is_none());
+        // A valid base-36 number, but packed for another arena size.
+        assert!(decode_run("1").is_none());
+    }
+
+    #[test]
+    fn stale_segment_is_dropped_instead_of_panicking() {
+        let mut world = world();
+        let snake = [pos(5, 5), pos(5, 4), pos(5, 3)];
+        spawn_at(&mut world, &snake, Direction::Up);
+        let stale = world.resource::<SnakeSegments>().0[1];
+        world.despawn(stale);
+
+        world.run_system_once(snake_movement);
+
+        assert_eq!(world.resource::<SnakeSegments>().0.len(), 2);
+        assert_eq!(body(&world)[0], pos(5, 6));
+    }
+
+    #[test]
+    fn eat_radius_reaches_nearby_food() {
+        let mut world = world();
+        world.insert_resource(EatRadius(1));
+        spawn_at(&mut world, &[pos(5, 5), pos(5, 4)], Direction::Up);
+        add_food(&mut world, pos(6, 5));
+        let far = add_food(&mut world, pos(7, 5));
+
+        world.run_system_once(snake_eating);
+
+        assert_eq!(world.resource::<Score>().0, 1);
+        assert_eq!(count::<Food>(&mut world), 1);
+        assert!(world.get_entity(far).is_some());
+    }
+
+    #[test]
+    fn eat_radius_setting_is_range_checked() {
+        let radius = |value| EatRadius::from_settings(&settings("eat_radius", value));
+        assert_eq!(radius("2").unwrap().0, 2);
+        assert_eq!(radius("0").unwrap().0, 0);
+        assert!(radius("4").is_err());
+        assert!(radius("-1").is_err());
+        assert_eq!(EatRadius::from_settings(&Settings::default()).unwrap().0, 0);
+    }
+
+    #[test]
+    fn tier_picks_follow_the_weights() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let weights = FoodTierWeights::default();
+        let mut picks = [0; 3];
+        for _ in 0..10_000 {
+            let tier = weights.pick(&mut rng);
+            picks[FoodTier::ALL.iter().position(|&t| t == tier).unwrap()] += 1;
+        }
+        for (tier, picked) in FoodTier::ALL.into_iter().zip(picks) {
+            let expected = weights.weight(tier) * 100;
+            assert!(picked.abs_diff(expected) < 300, "{picked} vs {expected}");
+        }
+
+        let only_rare = FoodTierWeights {
+            common: 0,
+            uncommon: 0,
+            rare: 1,
+        };
+        assert!((0..100).all(|_| only_rare.pick(&mut rng) == FoodTier::Rare));
+    }
+
+    #[test]
+    fn food_weights_setting_needs_three_weights_not_all_zero() {
+        let weights = |value| FoodTierWeights::from_settings(&settings("food_weights", value));
+        let parsed = weights("1, 2,3").unwrap();
+        assert_eq!((parsed.common, parsed.uncommon, parsed.rare), (1, 2, 3));
+        assert!(weights("0,0,5").is_ok());
+        assert!(weights("0,0,0").is_err());
+        assert!(weights("80,15").is_err());
+        assert!(weights("80,15,5,1").is_err());
+        assert!(weights("80,-15,5").is_err());
+    }
+
+    #[test]
+    fn wall_grace_holds_the_snake_for_a_turn() {
+        let mut world = world();
+        world.resource_mut::<WallGrace>().enabled = true;
+        spawn_at(&mut world, &[pos(9, 5), pos(8, 5)], Direction::Right);
+
+        world.run_system_once(snake_movement);
+        assert_eq!(body(&world), [pos(9, 5), pos(8, 5)]);
+        assert!(world.resource::<WallGrace>().holding);
+
+        world
+            .resource_mut::<InputBuffer>()
+            .0
+            .push_back(Direction::Up);
+        world.run_system_once(snake_movement);
+
+        assert_eq!(body(&world), [pos(9, 6), pos(9, 5)]);
+        assert!(!world.resource::<WallGrace>().holding);
+        assert_eq!(sent(&world, GameEvent::GameOver), 0);
+    }
+
+    #[test]
+    fn wall_grace_kills_if_the_snake_keeps_going() {
+        let mut world = world();
+        world.resource_mut::<WallGrace>().enabled = true;
+        spawn_at(&mut world, &[pos(9, 5), pos(8, 5)], Direction::Right);
+
+        world.run_system_once(snake_movement);
+        assert_eq!(sent(&world, GameEvent::GameOver), 0);
+        world.run_system_once(snake_movement);
+
+        assert_eq!(sent(&world, GameEvent::GameOver), 1);
+    }
+
+    #[test]
+    fn game_over_resets_the_run() {
+        let mut world = world();
+        let snake = [pos(9, 5), pos(8, 5), pos(7, 5), pos(6, 5)];
+        spawn_at(&mut world, &snake, Direction::Right);
+        let old = world.resource::<SnakeSegments>().0.clone();
+        add_food(&mut world, pos(1, 1));
+        world.resource_mut::<Score>().0 = 12;
+        world
+            .resource_mut::<InputBuffer>()
+            .0
+            .push_back(Direction::Up);
+        world.resource_mut::<LastRegisteredDir>().0 = Some(Direction::Up);
+        world.resource_mut::<TurnsSinceEat>().0 = Some(3);
+        world.resource_mut::<PendingGrowth>().0.push(4);
+        world.resource_mut::<LastTailPosition>().0 = Some(pos(5, 5));
+        world.resource_mut::<RunPath>().0.push(pos(9, 5));
+        world.resource_mut::<WallGrace>().holding = true;
+        world.resource_mut::<ActiveEffect>().0 =
+            Some((PowerUp::BigHead, Timer::from_seconds(1.0, TimerMode::Once)));
+
+        die(&mut world);
+
+        assert_eq!(world.resource::<Score>().0, 0);
+        assert!(world.resource::<InputBuffer>().0.is_empty());
+        assert_eq!(world.resource::<LastRegisteredDir>().0, None);
+        assert_eq!(world.resource::<TurnsSinceEat>().0, None);
+        assert!(world.resource::<PendingGrowth>().0.is_empty());
+        assert_eq!(world.resource::<LastTailPosition>().0, None);
+        assert!(world.resource::<RunPath>().0.is_empty());
+        assert!(!world.resource::<WallGrace>().holding);
+        assert!(world.resource::<ActiveEffect>().0.is_none());
+
+        assert_eq!(count::<Food>(&mut world), 0);
+        assert!(old
+            .iter()
+            .all(|&segment| world.get_entity(segment).is_none()));
+        assert_eq!(count::<SnakeHead>(&mut world), 1);
+        assert_eq!(body(&world), [arena_center(), pos(5, 4)]);
+    }
+
+    #[test]
+    fn length_scoring_grows_with_the_snake() {
+        assert_eq!(ScoringMode::Flat.award(3, 2), 3);
+        assert_eq!(ScoringMode::Flat.award(3, 40), 3);
+        assert_eq!(ScoringMode::Length.award(1, 2), 3);
+        assert_eq!(ScoringMode::Length.award(1, 40), 41);
+    }
+
+    #[test]
+    fn length_scoring_counts_the_snake_before_it_grows() {
+        let mut world = world();
+        world.insert_resource(ScoringMode::Length);
+        spawn_at(
+            &mut world,
+            &[pos(5, 5), pos(5, 4), pos(5, 3)],
+            Direction::Up,
+        );
+        add_food(&mut world, pos(5, 5));
+
+        world.run_system_once(snake_eating);
+
+        assert_eq!(world.resource::<Score>().0, 1 + 3);
+    }
+
+    #[test]
+    fn reachable_area_stops_at_blocked_cells() {
+        let open = reachable_area(pos(0, 0), &HashSet::new());
+        assert_eq!(open.len(), (ARENA_WIDTH * ARENA_HEIGHT) as usize);
+
+        // A wall along x = 3 cuts off everything to its right.
+        let wall: HashSet<Position> = (0..ARENA_HEIGHT as i32).map(|y| pos(3, y)).collect();
+        let left = reachable_area(pos(0, 0), &wall);
+        assert_eq!(left.len(), 3 * ARENA_HEIGHT as usize);
+        assert!(left.iter().all(|cell| cell.x < 3));
+
+        assert!(reachable_area(pos(3, 0), &wall).is_empty());
+        assert!(reachable_area(pos(-1, 0), &HashSet::new()).is_empty());
+    }
+
+    #[test]
+    fn start_setting_takes_a_cell_a_heading_or_both() {
+        let start = |value: &str| Start::from_settings(&settings("start", value));
+
+        let both = start("3,4 right").unwrap();
+        assert_eq!(both.cell, Some(pos(3, 4)));
+        assert_eq!(both.direction, Direction::Right);
+
+        let heading = start("left").unwrap();
+        assert_eq!(heading.cell, None);
+        assert_eq!(heading.direction, Direction::Left);
+
+        // The far corners of whatever size the arena is compiled with; only
+        // 10x10 is ever run.
+        let (right, top) = (ARENA_WIDTH as i32 - 1, ARENA_HEIGHT as i32 - 1);
+        let cell = start(&format!("0,{top}")).unwrap();
+        assert_eq!(cell.cell, Some(pos(0, top)));
+        assert_eq!(cell.direction, Direction::Up);
+        assert_eq!(
+            start(&format!("{right},0")).unwrap().cell,
+            Some(pos(right, 0))
+        );
+
+        assert!(start(&format!("{},4", right + 1)).is_err());
+        assert!(start(&format!("4,{}", top + 1)).is_err());
+        assert!(start("3;4").is_err());
+        assert!(start("sideways").is_err());
+    }
+
+    #[test]
+    fn rotations_are_quarter_turns() {
+        assert_eq!(Direction::Up.rotated_left(), Direction::Left);
+        assert_eq!(Direction::Up.rotated_right(), Direction::Right);
+        for direction in Direction::ALL {
+            assert_eq!(direction.rotated_left().rotated_right(), direction);
+            assert_eq!(
+                direction.rotated_left().rotated_left(),
+                direction.opposite()
+            );
+            assert_eq!(
+                direction.rotated_right().rotated_right(),
+                direction.opposite()
+            );
+            let left = direction.rotated_left();
+            assert_ne!(left, direction);
+            assert_ne!(left, direction.opposite());
+        }
+    }
+
+    #[test]
+    fn relative_controls_turn_from_the_last_queued_heading() {
+        let mut world = world();
+        world.insert_resource(ControlScheme::Relative);
+        spawn_at(&mut world, &[pos(5, 5), pos(5, 4)], Direction::Up);
+
+        press(&mut world, KeyCode::ArrowLeft);
+        input_frame(&mut world);
+        // Held, it doesn't turn again.
+        input_frame(&mut world);
+        release(&mut world, KeyCode::ArrowLeft);
+        press(&mut world, KeyCode::ArrowRight);
+        input_frame(&mut world);
+
+        assert_eq!(queued(&world), [Direction::Left, Direction::Up]);
+    }
+
+    fn camera_offset(world: &mut World) -> Vec2 {
+        world
+            .query_filtered::<&Transform, With<Camera2d>>()
+            .single(world)
+            .translation
+            .truncate()
+    }
+
+    #[test]
+    fn shake_jolts_the_camera_then_settles() {
+        let mut world = world();
+        world.spawn(Camera2dBundle::default());
+        // `screen_shake` keeps its timer in a `Local`, so run one instance.
+        let mut shake = IntoSystem::into_system(screen_shake);
+        shake.initialize(&mut world);
+
+        world
+            .resource_mut::<Events<GameEvent>>()
+            .send(GameEvent::GameOver);
+        world
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_secs_f32(SHAKE_SECONDS / 10.0));
+        shake.run((), &mut world);
+        let jolt = camera_offset(&mut world);
+        assert!(jolt.x.abs() <= SHAKE_INTENSITY && jolt.y.abs() <= SHAKE_INTENSITY);
+
+        world
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_secs_f32(SHAKE_SECONDS));
+        shake.run((), &mut world);
+        assert_eq!(camera_offset(&mut world), Vec2::ZERO);
+    }
+
+    #[test]
+    fn zero_intensity_never_moves_the_camera() {
+        let mut world = world();
+        world.resource_mut::<ScreenShake>().intensity = 0.0;
+        let camera = world.spawn(Camera2dBundle::default()).id();
+        world.get_mut::<Transform>(camera).unwrap().translation.x = 5.0;
+
+        world
+            .resource_mut::<Events<GameEvent>>()
+            .send(GameEvent::GameOver);
+        world
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_secs_f32(SHAKE_SECONDS / 10.0));
+        world.run_system_once(screen_shake);
+
+        assert_eq!(camera_offset(&mut world), Vec2::ZERO);
+    }
+
+    #[test]
+    fn inverted_controls_still_cannot_reverse() {
+        let mut world = world();
+        world.resource_mut::<InvertControls>().0 = true;
+        spawn_at(&mut world, &[pos(5, 5), pos(5, 4)], Direction::Up);
+
+        // Up flips to down, straight back into the body.
+        press(&mut world, KeyCode::ArrowUp);
+        input_frame(&mut world);
+        assert!(queued(&world).is_empty());
+
+        release(&mut world, KeyCode::ArrowUp);
+        press(&mut world, KeyCode::ArrowLeft);
+        input_frame(&mut world);
+        assert_eq!(queued(&world), [Direction::Right]);
+    }
+
+    #[test]
+    fn opposite_is_an_involution() {
+        for direction in Direction::ALL {
+            assert_ne!(direction.opposite(), direction);
+            assert_eq!(direction.opposite().opposite(), direction);
+        }
+    }
+
+    #[test]
+    fn step_moves_one_cell_and_back() {
+        let at = pos(3, 3);
+        assert_eq!(at.step(Direction::Up), pos(3, 4));
+        assert_eq!(at.step(Direction::Down), pos(3, 2));
+        assert_eq!(at.step(Direction::Left), pos(2, 3));
+        assert_eq!(at.step(Direction::Right), pos(4, 3));
+        for direction in Direction::ALL {
+            let next = at.step(direction);
+            assert_eq!(at.manhattan(next), 1);
+            assert_eq!(at.direction_to(next), Some(direction));
+            assert_eq!(next.step(direction.opposite()), at);
+        }
+    }
+
+    #[test]
+    fn reversal_is_never_queued() {
+        for heading in Direction::ALL {
+            let mut world = world();
+            let head = pos(5, 5);
+            spawn_at(&mut world, &[head, head.step(heading.opposite())], heading);
+
+            let key = match heading.opposite() {
+                Direction::Left => KeyCode::ArrowLeft,
+                Direction::Right => KeyCode::ArrowRight,
+                Direction::Up => KeyCode::ArrowUp,
+                Direction::Down => KeyCode::ArrowDown,
+            };
+            press(&mut world, key);
+            input_frame(&mut world);
+
+            assert!(queued(&world).is_empty());
+        }
+    }
+
+    #[test]
+    fn normal_head_covers_its_own_cell() {
+        let head = pos(5, 5);
+        for heading in Direction::ALL {
+            assert_eq!(ActiveEffect::default().head_cells(head, heading), [head]);
+        }
+    }
+
+    #[test]
+    fn big_head_covers_a_block_ahead_and_never_the_neck() {
+        let effect = big_head();
+        let head = pos(5, 5);
+        for heading in Direction::ALL {
+            let cells = effect.head_cells(head, heading);
+            let distinct: HashSet<Position> = cells.iter().copied().collect();
+            assert_eq!(distinct.len(), 4);
+            assert_eq!(cells[0], head);
+            assert!(cells.contains(&head.step(heading)));
+            assert!(cells.contains(&head.step(heading.rotated_right())));
+            assert!(!cells.contains(&head.step(heading.opposite())));
+        }
+    }
+
+    #[test]
+    fn big_head_eats_food_under_any_of_its_cells() {
+        let mut world = world();
+        world.insert_resource(big_head());
+        spawn_at(&mut world, &[pos(5, 5), pos(5, 4)], Direction::Up);
+        add_food(&mut world, pos(6, 6));
+        add_food(&mut world, pos(4, 6));
+
+        world.run_system_once(snake_eating);
+
+        assert_eq!(world.resource::<Score>().0, 1);
+        assert_eq!(count::<Food>(&mut world), 1);
+    }
+
+    #[test]
+    fn big_head_dies_when_any_of_it_hits_a_wall() {
+        let mut world = world();
+        world.insert_resource(big_head());
+        spawn_at(&mut world, &[pos(5, 5), pos(5, 4)], Direction::Up);
+        add_wall(&mut world, pos(6, 7));
+
+        world.run_system_once(snake_movement);
+
+        assert_eq!(sent(&world, GameEvent::GameOver), 1);
+    }
+
+    /// Curls back up beside the head, so (6, 6) is just right of where the
+    /// head moves next.
+    const CURLED: [Position; 6] = [
+        Position { x: 5, y: 5 },
+        Position { x: 5, y: 4 },
+        Position { x: 6, y: 4 },
+        Position { x: 6, y: 5 },
+        Position { x: 6, y: 6 },
+        Position { x: 7, y: 6 },
+    ];
+
+    #[test]
+    fn big_head_dies_when_any_of_it_hits_the_body() {
+        let mut world = world();
+        world.insert_resource(big_head());
+        spawn_at(&mut world, &CURLED, Direction::Up);
+
+        world.run_system_once(snake_movement);
+
+        assert_eq!(sent(&world, GameEvent::GameOver), 1);
+    }
+
+    #[test]
+    fn normal_head_passes_beside_the_same_body() {
+        let mut world = world();
+        spawn_at(&mut world, &CURLED, Direction::Up);
+
+        world.run_system_once(snake_movement);
+
+        assert_eq!(sent(&world, GameEvent::GameOver), 0);
+    }
+
+    #[test]
+    fn spawn_protection_is_off_by_default() {
+        let mut world = world();
+        spawn_at(&mut world, &[pos(9, 5), pos(8, 5)], Direction::Right);
+
+        die(&mut world);
+
+        assert!(world.resource::<ActiveEffect>().0.is_none());
+    }
+
+    #[test]
+    fn spawn_protection_ignores_collisions_for_its_window() {
+        let mut world = world();
+        world.insert_resource(SpawnProtection(SPAWN_PROTECTION_SECONDS));
+        spawn_at(&mut world, &[pos(9, 5), pos(8, 5)], Direction::Right);
+
+        die(&mut world);
+        assert!(world.resource::<ActiveEffect>().is(PowerUp::Shield));
+        world.resource_mut::<Events<GameEvent>>().clear();
+        add_wall(&mut world, pos(5, 6));
+
+        world.run_system_once(snake_movement);
+        assert_eq!(body(&world), [pos(5, 6), pos(5, 5)]);
+        assert_eq!(sent(&world, GameEvent::GameOver), 0);
+
+        // Once the window runs out, the next wall is fatal again.
+        world
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_secs_f32(SPAWN_PROTECTION_SECONDS));
+        world.run_system_once(tick_active_effect);
+        add_wall(&mut world, pos(5, 7));
+        world.run_system_once(snake_movement);
+        assert_eq!(sent(&world, GameEvent::GameOver), 1);
+    }
+
+    #[test]
+    fn tron_trail_walls_off_the_cells_left_behind() {
+        let mut world = world();
+        world.insert_resource(MovementStyle::Tron);
+        spawn_at(&mut world, &[pos(5, 5), pos(4, 5)], Direction::Right);
+
+        world.run_system_once(snake_movement);
+        let trail: Vec<Position> = world
+            .query_filtered::<&Position, With<RunWall>>()
+            .iter(&world)
+            .copied()
+            .collect();
+        assert_eq!(trail, [pos(4, 5)]);
+
+        // Loop back round into the trail.
+        for turn in [Direction::Down, Direction::Left, Direction::Up] {
+            assert_eq!(sent(&world, GameEvent::GameOver), 0);
+            world.resource_mut::<InputBuffer>().0.push_back(turn);
+            world.run_system_once(snake_movement);
+        }
+        assert_eq!(sent(&world, GameEvent::GameOver), 1);
+    }
+
+    #[test]
+    fn tap_within_one_frame_still_turns() {
+        let mut world = world();
+        spawn_at(&mut world, &[pos(5, 5), pos(5, 4)], Direction::Up);
+
+        // Pressed and released between two samples, as at a low frame rate.
+        press(&mut world, KeyCode::ArrowLeft);
+        release(&mut world, KeyCode::ArrowLeft);
+        input_frame(&mut world);
+
+        assert_eq!(queued(&world), [Direction::Left]);
+    }
+
+    #[test]
+    fn turns_per_hold_do_not_depend_on_the_frame_rate() {
+        for frames in [2, 8, 64] {
+            let mut world = world();
+            spawn_at(&mut world, &[pos(5, 5), pos(5, 4)], Direction::Up);
+
+            press(&mut world, KeyCode::ArrowRight);
+            for _ in 0..frames {
+                input_frame(&mut world);
+            }
+            release(&mut world, KeyCode::ArrowRight);
+            for _ in 0..frames {
+                input_frame(&mut world);
+            }
+
+            assert_eq!(queued(&world), [Direction::Right]);
+        }
+    }
+
+    #[test]
+    fn scenario_parses_heading_cells_and_food() {
+        let scenario = Scenario::parse("right 5,5 4,5 3,5 food 8,5").unwrap();
+        assert_eq!(scenario.direction, Direction::Right);
+        assert_eq!(scenario.cells, [pos(5, 5), pos(4, 5), pos(3, 5)]);
+        assert_eq!(scenario.food, Some(pos(8, 5)));
+
+        let err = Scenario::parse("up 2, 2 2,1").unwrap_err();
+        assert!(err.contains("cell"), "{err}");
+        assert!(Scenario::parse("sideways 1,1").is_err());
+        assert!(Scenario::parse("").is_err());
+        assert!(Scenario::parse("up 1;1").is_err());
+        assert!(Scenario::parse("up 1,x").is_err());
+        assert!(Scenario::parse("up 1,1 food").is_err());
+    }
+
+    #[test]
+    fn spawn_snake_from_rejects_impossible_layouts() {
+        let mut world = world();
+        let up = Direction::Up;
+        assert!(try_spawn(&mut world, &[], up).is_err());
+        assert!(try_spawn(&mut world, &[pos(0, 0), pos(-1, 0)], up).is_err());
+        assert!(try_spawn(&mut world, &[pos(5, 5), pos(5, 3)], up).is_err());
+        assert!(try_spawn(&mut world, &[pos(5, 5), pos(6, 6)], up).is_err());
+        let doubled = [pos(5, 5), pos(5, 4), pos(5, 5)];
+        assert!(try_spawn(&mut world, &doubled, up).is_err());
+        assert_eq!(count::<SnakeSegment>(&mut world), 0);
+
+        try_spawn(&mut world, &[pos(5, 5), pos(5, 4), pos(4, 4)], up).unwrap();
+        assert_eq!(body(&world), [pos(5, 5), pos(5, 4), pos(4, 4)]);
+        assert_eq!(heading(&mut world), up);
+    }
+
+    #[test]
+    fn streak_counts_food_until_a_death() {
+        let mut world = world();
+        // `track_streak` reads each event once, so keep one instance.
+        let mut track = IntoSystem::into_system(track_streak);
+        track.initialize(&mut world);
+        let mut send = |world: &mut World, event| {
+            world.resource_mut::<Events<GameEvent>>().send(event);
+            track.run((), world);
+            world.resource::<Streak>().0
+        };
+
+        assert_eq!(send(&mut world, GameEvent::Ate), 1);
+        assert_eq!(send(&mut world, GameEvent::Growth), 1);
+        assert_eq!(send(&mut world, GameEvent::Ate), 2);
+        assert_eq!(send(&mut world, GameEvent::GameOver), 0);
+        assert_eq!(send(&mut world, GameEvent::Ate), 1);
+    }
+
+    #[test]
+    fn crossing_each_edge_is_one_death_and_one_respawn() {
+        let edges = [
+            (pos(0, 5), Direction::Left),
+            (pos(9, 5), Direction::Right),
+            (pos(5, 9), Direction::Up),
+            (pos(5, 0), Direction::Down),
+        ];
+        for (head, heading) in edges {
+            let mut world = world();
+            spawn_at(&mut world, &[head, head.step(heading.opposite())], heading);
+
+            world.run_system_once(snake_movement);
+            assert_eq!(sent(&world, GameEvent::GameOver), 1);
+            world.run_system_once(game_over);
+
+            assert_eq!(count::<SnakeHead>(&mut world), 1);
+            assert_eq!(count::<SnakeSegment>(&mut world), 2);
+            assert_eq!(body(&world), [arena_center(), pos(5, 4)]);
+        }
+    }
+
+    #[test]
+    fn big_head_crossing_an_edge_dies_once() {
+        let mut world = world();
+        world.insert_resource(big_head());
+        spawn_at(&mut world, &[pos(9, 5), pos(8, 5)], Direction::Right);
+
+        world.run_system_once(snake_movement);
+
+        assert_eq!(sent(&world, GameEvent::GameOver), 1);
+    }
+
+    #[test]
+    fn splitter_food_leaves_two_foods_beside_it() {
+        let mut world = world();
+        spawn_at(&mut world, &[pos(5, 5), pos(5, 4)], Direction::Up);
+        let splitter = add_food(&mut world, pos(5, 5));
+        world.entity_mut(splitter).insert(SplitterFood);
+
+        world.run_system_once(snake_eating);
+        assert_eq!(sent(&world, GameEvent::Split(pos(5, 5))), 1);
+        world.run_system_once(split_food);
+
+        let food: Vec<Position> = world
+            .query_filtered::<&Position, With<Food>>()
+            .iter(&world)
+            .copied()
+            .collect();
+        assert_eq!(food.len(), 2);
+        assert_ne!(food[0], food[1]);
+        for cell in food {
+            assert_eq!(cell.manhattan(pos(5, 5)), 1);
+            assert_ne!(cell, pos(5, 4));
+        }
+    }
+
+    #[test]
+    fn splitting_stops_at_the_food_cap() {
+        let mut world = world();
+        spawn_at(&mut world, &[pos(5, 5), pos(5, 4)], Direction::Up);
+        for x in 0..MAX_FOOD as i32 - 1 {
+            add_food(&mut world, pos(x, 0));
+        }
+        world
+            .resource_mut::<Events<GameEvent>>()
+            .send(GameEvent::Split(pos(5, 5)));
+
+        world.run_system_once(split_food);
+
+        assert_eq!(count::<Food>(&mut world), MAX_FOOD);
+    }
+
+    #[test]
+    fn reversal_guard_holds_for_both_key_triggers() {
+        for trigger in [KeyTrigger::Held, KeyTrigger::Tap] {
+            let mut world = world();
+            world.insert_resource(trigger);
+            spawn_at(&mut world, &[pos(5, 5), pos(5, 4)], Direction::Up);
+
+            press(&mut world, KeyCode::ArrowDown);
+            input_frame(&mut world);
+            input_frame(&mut world);
+
+            assert!(queued(&world).is_empty());
+        }
+    }
+
+    #[test]
+    fn held_key_hides_later_arrows_but_a_tap_does_not() {
+        for (trigger, expected) in [
+            (KeyTrigger::Held, vec![Direction::Left]),
+            (KeyTrigger::Tap, vec![Direction::Left, Direction::Down]),
+        ] {
+            let mut world = world();
+            world.insert_resource(trigger);
+            spawn_at(&mut world, &[pos(5, 5), pos(5, 4)], Direction::Up);
+
+            press(&mut world, KeyCode::ArrowLeft);
+            input_frame(&mut world);
+            // Left is still held when down goes in.
+            press(&mut world, KeyCode::ArrowDown);
+            input_frame(&mut world);
+
+            assert_eq!(queued(&world), expected);
+        }
+    }
+
+    #[test]
+    fn flags_read_on_and_off() {
+        let flag = |value| settings("grow_delay", value).flag("grow_delay");
+        assert!(flag("on").unwrap());
+        assert!(!flag("off").unwrap());
+        assert!(flag("yes").is_err());
+        assert!(!Settings::default().flag("grow_delay").unwrap());
+        assert!(Settings::default().flag_or("motion_blur", true).unwrap());
+        assert!(
+            !MotionBlur::from_settings(&settings("motion_blur", "off"))
+                .unwrap()
+                .0
+        );
+    }
+
+    #[test]
+    fn tail_severing_setting_turns_it_on() {
+        let severing = |value| TailSevering::from_settings(&settings("tail_severing", value));
+        assert!(severing("on").unwrap().0);
+        assert!(!severing("off").unwrap().0);
+        assert!(severing("cut").is_err());
+        assert!(!TailSevering::from_settings(&Settings::default()).unwrap().0);
+    }
+
+    #[test]
+    fn trap_spawn_setting_turns_it_on() {
+        let trap = |value| TrapSpawn::from_settings(&settings("trap_spawn", value));
+        assert!(trap("on").unwrap().0);
+        assert!(!trap("off").unwrap().0);
+        assert!(trap("corners").is_err());
+        assert!(!TrapSpawn::from_settings(&Settings::default()).unwrap().0);
+    }
+
+    #[test]
+    fn corpse_walls_setting_turns_it_on() {
+        let corpses = |value| CorpseWalls::from_settings(&settings("corpse_walls", value));
+        assert!(corpses("on").unwrap().0);
+        assert!(!corpses("off").unwrap().0);
+        assert!(corpses("1").is_err());
+        assert!(!CorpseWalls::from_settings(&Settings::default()).unwrap().0);
+    }
+
+    #[test]
+    fn wall_grace_setting_turns_it_on() {
+        let grace = |value| WallGrace::from_settings(&settings("wall_grace", value));
+        assert!(grace("on").unwrap().enabled);
+        assert!(!grace("on").unwrap().holding);
+        assert!(!grace("off").unwrap().enabled);
+        assert!(grace("true").is_err());
+        assert!(
+            !WallGrace::from_settings(&Settings::default())
+                .unwrap()
+                .enabled
+        );
+    }
+
+    #[test]
+    fn modes_override_only_their_own_knobs() {
+        let mut world = world();
+        world.insert_resource(CorpseWalls(true));
+        world.insert_resource(TailSevering(false));
+
+        GameMode::Zen.apply(&mut world);
+
+        assert!(world.resource::<CorpseWalls>().0);
+        assert!(world.resource::<TailSevering>().0);
+        assert!(world.resource::<ConstantSpeed>().enabled);
+        assert_eq!(world.resource::<ConstantSpeed>().interval, ZEN_INTERVAL);
+    }
+
+    #[test]
+    fn respawn_setting_picks_the_policy() {
+        let respawn = |value| RespawnPolicy::from_settings(&settings("respawn", value));
+        assert!(respawn("start").unwrap() == RespawnPolicy::Start);
+        assert!(respawn("near_death").unwrap() == RespawnPolicy::NearDeath);
+        assert!(respawn("center").is_err());
+        assert!(
+            RespawnPolicy::from_settings(&Settings::default()).unwrap() == RespawnPolicy::Start
+        );
+    }
+
+    #[test]
+    fn food_pulls_never_exceed_max_effects() {
+        let mut world = world();
+        world.insert_resource(MaxEffects(2));
+        world.insert_resource(EatRadius(2));
+        spawn_at(&mut world, &[pos(5, 5), pos(5, 4)], Direction::Up);
+        let live = |world: &mut World| {
+            world
+                .query_filtered::<(), (With<Lifetime>, Without<PathMarker>)>()
+                .iter(world)
+                .count()
+        };
+
+        for round in [
+            [pos(6, 5), pos(4, 5), pos(5, 6), pos(5, 7), pos(7, 5)],
+            [pos(3, 5), pos(6, 6), pos(4, 6), pos(6, 4), pos(4, 4)],
+        ] {
+            for at in round {
+                add_food(&mut world, at);
+            }
+            world.run_system_once(snake_eating);
+            assert_eq!(count::<Food>(&mut world), 0);
+            assert!(live(&mut world) <= 2);
+        }
+
+        assert_eq!(count::<FoodPull>(&mut world), 2);
+    }
+
+    #[test]
+    fn streak_bonus_pays_for_each_apple_in_the_streak() {
+        let mut world = world();
+        world.insert_resource(StreakBonus(2));
+        world.insert_resource(Streak(3));
+        spawn_at(&mut world, &[pos(5, 5), pos(5, 4)], Direction::Up);
+        add_food(&mut world, pos(5, 5));
+
+        world.run_system_once(snake_eating);
+
+        assert_eq!(world.resource::<Score>().0, 1 + 2 * 3);
+    }
+
+    #[test]
+    fn streak_bonus_is_off_by_default() {
+        let mut world = world();
+        world.insert_resource(Streak(3));
+        spawn_at(&mut world, &[pos(5, 5), pos(5, 4)], Direction::Up);
+        add_food(&mut world, pos(5, 5));
+
+        world.run_system_once(snake_eating);
+
+        assert_eq!(world.resource::<Score>().0, 1);
+    }
+
+    #[test]
+    fn streak_bonus_setting_is_range_checked() {
+        let bonus = |value| StreakBonus::from_settings(&settings("streak_bonus", value));
+        assert_eq!(bonus("5").unwrap().0, 5);
+        assert!(bonus("101").is_err());
+        assert!(bonus("-1").is_err());
+        assert_eq!(
+            StreakBonus::from_settings(&Settings::default()).unwrap().0,
+            0
+        );
+    }
+
+    #[test]
+    fn scenario_layout_plays_out_to_eating_its_food() {
+        let mut world = world();
+        let scenario = Scenario::parse("up 5,5 5,4 4,4 3,4 food 5,6").unwrap();
+        spawn_at(&mut world, &scenario.cells, scenario.direction);
+        add_food(&mut world, scenario.food.unwrap());
+
+        world.run_system_once(snake_movement);
+        world.run_system_once(snake_eating);
+        world.run_system_once(snake_growth);
+
+        assert_eq!(world.resource::<Score>().0, 1);
+        assert_eq!(count::<Food>(&mut world), 0);
+        assert_eq!(
+            body(&world),
+            [pos(5, 6), pos(5, 5), pos(5, 4), pos(4, 4), pos(3, 4)]
+        );
+    }
+
+    #[test]
+    fn translation_skips_unmoved_entities_until_a_resize() {
+        let mut world = world();
+        world.init_resource::<Events<WindowResized>>();
+        let window = world.spawn((Window::default(), PrimaryWindow)).id();
+        let grid = Grid::new(&Window::default());
+        let moved = world.spawn((pos(1, 1), Transform::default())).id();
+        let still = world.spawn((pos(3, 3), Transform::default())).id();
+        // Change detection compares against the last run, so keep one instance.
+        let mut translate = IntoSystem::into_system(position_translation);
+        translate.initialize(&mut world);
+        let at = |world: &World, entity| world.get::<Transform>(entity).unwrap().translation;
+
+        translate.run((), &mut world);
+        assert_eq!(at(&world, still), grid.cell_center(pos(3, 3)));
+
+        *world.get_mut::<Transform>(still).unwrap() = Transform::default();
+        *world.get_mut::<Position>(moved).unwrap() = pos(2, 2);
+        translate.run((), &mut world);
+        assert_eq!(at(&world, moved), grid.cell_center(pos(2, 2)));
+        assert_eq!(at(&world, still), Vec3::ZERO);
+
+        world.send_event(WindowResized {
+            window,
+            width: 500.0,
+            height: 500.0,
+        });
+        translate.run((), &mut world);
+        assert_eq!(at(&world, still), grid.cell_center(pos(3, 3)));
+    }
+}